@@ -0,0 +1,47 @@
+-- Manga tracked by at least one subscriber.
+CREATE TABLE manga (
+    id TEXT PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL
+);
+
+-- The latest chapter of a manga that has been announced in each language.
+CREATE TABLE latest_chapters (
+    manga_id TEXT NOT NULL REFERENCES manga (id) ON DELETE CASCADE,
+    language TEXT NOT NULL,
+    chapter_id TEXT NOT NULL,
+    number TEXT,
+    publish_at TEXT,
+    PRIMARY KEY (manga_id, language)
+);
+
+-- Channels and users that receive updates for a manga. Exactly one of `channel_id` and
+-- `user_id` is set.
+CREATE TABLE subscriptions (
+    id INTEGER PRIMARY KEY,
+    manga_id TEXT NOT NULL REFERENCES manga (id) ON DELETE CASCADE,
+    channel_id INTEGER,
+    user_id INTEGER,
+    guild_id INTEGER,
+    language TEXT NOT NULL,
+    role_id INTEGER,
+    CHECK ((channel_id IS NULL) <> (user_id IS NULL))
+);
+
+CREATE UNIQUE INDEX subscriptions_channel ON subscriptions (manga_id, channel_id)
+    WHERE channel_id IS NOT NULL;
+CREATE UNIQUE INDEX subscriptions_user ON subscriptions (manga_id, user_id)
+    WHERE user_id IS NOT NULL;
+CREATE INDEX subscriptions_subscriber ON subscriptions (channel_id, user_id);
+
+-- Guilds that have changed their settings from the defaults.
+CREATE TABLE guild_settings (
+    guild_id INTEGER PRIMARY KEY NOT NULL
+);
+
+-- The content ratings allowed in a guild.
+CREATE TABLE guild_content_ratings (
+    guild_id INTEGER NOT NULL REFERENCES guild_settings (guild_id) ON DELETE CASCADE,
+    content_rating TEXT NOT NULL
+        CHECK (content_rating IN ('safe', 'suggestive', 'erotica', 'pornographic')),
+    PRIMARY KEY (guild_id, content_rating)
+);
@@ -0,0 +1,20 @@
+-- Announcements of new chapters, recorded before they are delivered. Exactly one of
+-- `channel_id` and `user_id` is set.
+CREATE TABLE notifications (
+    id TEXT PRIMARY KEY NOT NULL,
+    manga_id TEXT NOT NULL,
+    manga_title TEXT NOT NULL,
+    cover_url TEXT,
+    channel_id INTEGER,
+    user_id INTEGER,
+    role_id INTEGER,
+    -- The announced chapters as a JSON array.
+    chapters TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'sending', 'sent', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    queued_at INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    CHECK ((channel_id IS NULL) <> (user_id IS NULL))
+);
+
+CREATE INDEX notifications_status ON notifications (status, queued_at, position);
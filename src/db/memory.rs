@@ -0,0 +1,234 @@
+//! Storage that keeps everything in memory.
+//!
+//! Nothing is persisted so this is mostly useful for testing or running the bot without a
+//! database.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use serenity::{
+    async_trait,
+    model::prelude::{ChannelId, GuildId},
+};
+
+use super::{
+    ChapterMarker, GuildSettings, Manga, Notification, NotificationStatus, Result, Store,
+    Subscriber, Subscription,
+};
+
+/// Storage that keeps all manga, guild settings and notifications in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    manga: Mutex<HashMap<String, Manga>>,
+    settings: Mutex<HashMap<GuildId, GuildSettings>>,
+    /// The outbox in the order notifications were queued.
+    notifications: Mutex<Vec<Notification>>,
+}
+
+impl MemoryStore {
+    /// Creates a new empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a notification from the outbox whatever its status.
+    #[cfg(test)]
+    pub fn notification(&self, id: &str) -> Option<Notification> {
+        self.notifications
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|n| n.id == id)
+            .cloned()
+    }
+
+    /// Applies a change to a manga, doing nothing if the manga isn't tracked.
+    fn modify(&self, manga_id: &str, f: impl FnOnce(&mut Manga)) {
+        if let Some(manga) = self.manga.lock().unwrap().get_mut(manga_id) {
+            f(manga);
+        }
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn read_manga(&self, manga_id: &str) -> Result<Option<Manga>> {
+        Ok(self.manga.lock().unwrap().get(manga_id).cloned())
+    }
+
+    async fn read_all_manga(&self) -> Result<Vec<Manga>> {
+        Ok(self.manga.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn read_subscribed_manga(&self, subscriber: Subscriber) -> Result<Vec<Manga>> {
+        Ok(self
+            .manga
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|m| m.subscription(subscriber).is_some())
+            .cloned()
+            .collect())
+    }
+
+    async fn create_manga(&self, manga: Manga) -> Result<()> {
+        self.manga.lock().unwrap().insert(manga.id.clone(), manga);
+        Ok(())
+    }
+
+    async fn delete_manga(&self, manga_id: &str) -> Result<()> {
+        self.manga.lock().unwrap().remove(manga_id);
+        Ok(())
+    }
+
+    async fn set_subscriptions(
+        &self,
+        manga_id: &str,
+        subscriptions: &[Subscription],
+    ) -> Result<()> {
+        self.modify(manga_id, |manga| manga.subscribers = subscriptions.to_vec());
+        Ok(())
+    }
+
+    async fn set_subscription_guild(
+        &self,
+        manga_id: &str,
+        channel_id: ChannelId,
+        guild_id: GuildId,
+    ) -> Result<()> {
+        self.modify(manga_id, |manga| {
+            let subscriber = Subscriber::Channel(channel_id);
+            if let Some(subscription) = manga
+                .subscribers
+                .iter_mut()
+                .find(|s| s.subscriber == subscriber)
+            {
+                subscription.guild_id = Some(guild_id);
+            }
+        });
+        Ok(())
+    }
+
+    async fn set_latest_chapter(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+    ) -> Result<()> {
+        self.modify(manga_id, |manga| {
+            manga
+                .latest_chapters
+                .insert(language.to_owned(), marker.clone());
+        });
+        Ok(())
+    }
+
+    async fn read_guild_settings(&self, guild_id: GuildId) -> Result<GuildSettings> {
+        Ok(self
+            .settings
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_else(|| GuildSettings::new(guild_id)))
+    }
+
+    async fn read_all_guild_settings(&self) -> Result<Vec<GuildSettings>> {
+        Ok(self.settings.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn save_guild_settings(&self, settings: GuildSettings) -> Result<()> {
+        self.settings
+            .lock()
+            .unwrap()
+            .insert(settings.guild_id, settings);
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn enqueue_notifications(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+        notifications: &[Notification],
+    ) -> Result<()> {
+        let mut outbox = self.notifications.lock().unwrap();
+        for notification in notifications {
+            if !outbox.iter().any(|n| n.id == notification.id) {
+                outbox.push(notification.clone());
+            }
+        }
+
+        self.modify(manga_id, |manga| {
+            manga
+                .latest_chapters
+                .insert(language.to_owned(), marker.clone());
+        });
+        Ok(())
+    }
+
+    async fn read_pending_notifications(&self) -> Result<Vec<Notification>> {
+        Ok(self
+            .notifications
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|n| n.status == NotificationStatus::Pending)
+            .cloned()
+            .collect())
+    }
+
+    async fn claim_notification(&self, id: &str, attempts: u32) -> Result<bool> {
+        let mut outbox = self.notifications.lock().unwrap();
+        match outbox
+            .iter_mut()
+            .find(|n| n.id == id && n.status == NotificationStatus::Pending)
+        {
+            Some(notification) => {
+                notification.status = NotificationStatus::Sending;
+                notification.attempts = attempts;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn set_notification_status(
+        &self,
+        id: &str,
+        status: NotificationStatus,
+        attempts: u32,
+    ) -> Result<()> {
+        let mut outbox = self.notifications.lock().unwrap();
+        if let Some(notification) = outbox.iter_mut().find(|n| n.id == id) {
+            notification.status = status;
+            notification.attempts = attempts;
+        }
+        Ok(())
+    }
+
+    async fn fail_interrupted_notifications(&self) -> Result<u64> {
+        let mut count = 0;
+        for notification in self.notifications.lock().unwrap().iter_mut() {
+            if notification.status == NotificationStatus::Sending {
+                notification.status = NotificationStatus::Failed;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    async fn prune_notifications(&self, before: i64) -> Result<()> {
+        self.notifications.lock().unwrap().retain(|n| {
+            n.queued_at >= before
+                || !matches!(
+                    n.status,
+                    NotificationStatus::Sent | NotificationStatus::Failed
+                )
+        });
+        Ok(())
+    }
+}
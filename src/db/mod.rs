@@ -0,0 +1,539 @@
+//! The `db` module contains types and functions for interacting with the database.
+//!
+//! Storage is abstracted behind the [Store] trait. In production this application uses either
+//! an Azure Cosmos MongoDB NoSQL database ([MongoClient]) or a SQLite database ([SqliteStore])
+//! to store the manga tracked by various channels, while [MemoryStore] keeps everything in
+//! memory which is useful for testing.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    model::prelude::{ChannelId, GuildId, RoleId, UserId},
+};
+
+use crate::mangadex::{self, ContentRating};
+
+pub use self::memory::MemoryStore;
+pub use self::mongo::MongoClient;
+pub use self::sqlite::SqliteStore;
+
+mod memory;
+mod mongo;
+mod sqlite;
+
+/// Models a manga as it appears in the database.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "MangaRepr")]
+pub struct Manga {
+    /// The Id of the manga from MangaDex.
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// The english (or equivalent) title of the manga.
+    pub title: String,
+    /// The latest chapter announced for this manga keyed by language.
+    #[serde(default)]
+    pub latest_chapters: HashMap<String, ChapterMarker>,
+    /// The channels and users that are tracking this manga.
+    ///
+    /// Stored as `channels` since only channels could track manga at one point.
+    #[serde(rename = "channels")]
+    pub subscribers: Vec<Subscription>,
+}
+
+impl Manga {
+    /// Gets the subscription for a given subscriber if it is tracking this manga.
+    pub fn subscription(&self, subscriber: Subscriber) -> Option<&Subscription> {
+        self.subscribers.iter().find(|s| s.subscriber == subscriber)
+    }
+}
+
+/// Manga used to only track the latest chapter in the default language, so those fields are
+/// accepted when reading from the database and become its marker in that language.
+#[derive(Deserialize)]
+struct MangaRepr {
+    #[serde(rename = "_id")]
+    id: String,
+    title: String,
+    #[serde(default)]
+    latest_chapters: HashMap<String, ChapterMarker>,
+    #[serde(default)]
+    latest_chapter_id: Option<String>,
+    #[serde(default)]
+    latest_chapter_number: Option<String>,
+    #[serde(default)]
+    latest_publish_at: Option<String>,
+    #[serde(rename = "channels")]
+    subscribers: Vec<Subscription>,
+}
+
+impl From<MangaRepr> for Manga {
+    fn from(value: MangaRepr) -> Self {
+        let mut latest_chapters = value.latest_chapters;
+        if let Some(id) = value.latest_chapter_id {
+            latest_chapters
+                .entry(String::from(mangadex::DEFAULT_LANGUAGE))
+                .or_insert(ChapterMarker {
+                    id,
+                    number: value.latest_chapter_number,
+                    publish_at: value.latest_publish_at,
+                });
+        }
+
+        Manga {
+            id: value.id,
+            title: value.title,
+            latest_chapters,
+            subscribers: value.subscribers,
+        }
+    }
+}
+
+/// Something that receives updates for a manga.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Subscriber {
+    /// A channel in which updates are announced.
+    #[serde(rename = "channel_id")]
+    Channel(ChannelId),
+    /// A user which is sent updates as direct messages.
+    #[serde(rename = "user_id")]
+    User(UserId),
+}
+
+/// Models a subscription to updates for a manga.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "SubscriptionRepr")]
+pub struct Subscription {
+    /// Where updates are sent to.
+    #[serde(flatten)]
+    pub subscriber: Subscriber,
+    /// The id of the guild that the channel belongs to, if any.
+    pub guild_id: Option<GuildId>,
+    /// The translated language of the chapters to announce.
+    pub language: String,
+    /// The role to mention when announcing chapters, if any.
+    pub role_id: Option<RoleId>,
+}
+
+/// Subscriptions used to be stored as bare channel ids so either form is accepted when
+/// reading from the database.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SubscriptionRepr {
+    Legacy(ChannelId),
+    Current {
+        #[serde(flatten)]
+        subscriber: Subscriber,
+        #[serde(default)]
+        guild_id: Option<GuildId>,
+        language: String,
+        #[serde(default)]
+        role_id: Option<RoleId>,
+    },
+}
+
+impl From<SubscriptionRepr> for Subscription {
+    fn from(value: SubscriptionRepr) -> Self {
+        match value {
+            SubscriptionRepr::Legacy(channel_id) => Subscription {
+                subscriber: Subscriber::Channel(channel_id),
+                guild_id: None,
+                language: String::from(mangadex::DEFAULT_LANGUAGE),
+                role_id: None,
+            },
+            SubscriptionRepr::Current {
+                subscriber,
+                guild_id,
+                language,
+                role_id,
+            } => Subscription {
+                subscriber,
+                guild_id,
+                language,
+                role_id,
+            },
+        }
+    }
+}
+
+/// Marks the latest chapter of a manga that has been announced in a specific language.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChapterMarker {
+    /// The id of the chapter.
+    pub id: String,
+    /// The chapter number of the chapter.
+    pub number: Option<String>,
+    /// When the chapter was published.
+    pub publish_at: Option<String>,
+}
+
+impl From<mangadex::Chapter> for ChapterMarker {
+    fn from(value: mangadex::Chapter) -> Self {
+        Self {
+            id: value.id,
+            number: value.attributes.chapter,
+            publish_at: value.attributes.publish_at,
+        }
+    }
+}
+
+/// Models an announcement of new chapters to a single subscriber as it appears in the outbox.
+///
+/// Announcements are recorded in the outbox before they are delivered so that none are lost
+/// or delivered twice if the bot stops part way through a scan.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Notification {
+    /// Identifies the announcement by its subscriber and the last chapter it covers, so the
+    /// same chapter is never queued twice for the same subscriber.
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// The Id of the manga from MangaDex.
+    pub manga_id: String,
+    /// The english (or equivalent) title of the manga.
+    pub manga_title: String,
+    /// The URL of the manga's cover art, if any.
+    pub cover_url: Option<String>,
+    /// Where the announcement is sent to.
+    #[serde(flatten)]
+    pub subscriber: Subscriber,
+    /// The role to mention in the announcement, if any.
+    pub role_id: Option<RoleId>,
+    /// The chapters being announced. Announcements of more than one chapter are sent as a
+    /// single summary message.
+    pub chapters: Vec<AnnouncedChapter>,
+    /// How far along delivery is.
+    pub status: NotificationStatus,
+    /// The number of times delivery has been attempted.
+    pub attempts: u32,
+    /// When the announcement was queued as a unix timestamp.
+    pub queued_at: i64,
+    /// The order of the announcement among those queued at the same time.
+    pub position: u32,
+}
+
+impl Notification {
+    /// Creates a pending announcement of some chapters of a manga to a subscription.
+    ///
+    /// `chapters` must not be empty.
+    pub fn new(
+        manga: &Manga,
+        subscription: &Subscription,
+        chapters: Vec<AnnouncedChapter>,
+        cover_url: Option<String>,
+        queued_at: i64,
+        position: u32,
+    ) -> Self {
+        let subscriber = match subscription.subscriber {
+            Subscriber::Channel(channel_id) => format!("channel-{channel_id}"),
+            Subscriber::User(user_id) => format!("user-{user_id}"),
+        };
+        let last = chapters.last().map(|c| c.id.as_str()).unwrap_or_default();
+
+        Self {
+            id: format!("{subscriber}:{last}"),
+            manga_id: manga.id.clone(),
+            manga_title: manga.title.clone(),
+            cover_url,
+            subscriber: subscription.subscriber,
+            role_id: subscription.role_id,
+            chapters,
+            status: NotificationStatus::Pending,
+            attempts: 0,
+            queued_at,
+            position,
+        }
+    }
+}
+
+/// How far along the delivery of a notification is.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationStatus {
+    /// Waiting to be delivered.
+    Pending,
+    /// Being delivered. Notifications left in this state were interrupted and may or may not
+    /// have been delivered.
+    Sending,
+    /// Delivered.
+    Sent,
+    /// Given up on.
+    Failed,
+}
+
+impl NotificationStatus {
+    /// Every status.
+    pub const ALL: [NotificationStatus; 4] = [
+        NotificationStatus::Pending,
+        NotificationStatus::Sending,
+        NotificationStatus::Sent,
+        NotificationStatus::Failed,
+    ];
+
+    /// The name of the status as it is stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationStatus::Pending => "pending",
+            NotificationStatus::Sending => "sending",
+            NotificationStatus::Sent => "sent",
+            NotificationStatus::Failed => "failed",
+        }
+    }
+}
+
+/// The details of a chapter needed to announce it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnnouncedChapter {
+    /// The id of the chapter.
+    pub id: String,
+    /// The chapter number of the chapter.
+    pub number: Option<String>,
+    /// The title of the chapter.
+    pub title: Option<String>,
+    /// The volume the chapter belongs to.
+    pub volume: Option<String>,
+    /// The number of pages in the chapter.
+    pub pages: i32,
+    /// The name of the scanlation group that translated the chapter.
+    pub group: Option<String>,
+    /// When the chapter became readable.
+    pub readable_at: Option<String>,
+}
+
+impl From<&mangadex::Chapter> for AnnouncedChapter {
+    fn from(value: &mangadex::Chapter) -> Self {
+        Self {
+            id: value.id.clone(),
+            number: value.attributes.chapter.clone(),
+            title: value.attributes.title.clone(),
+            volume: value.attributes.volume.clone(),
+            pages: value.attributes.pages,
+            group: value.scanlation_group().map(String::from),
+            readable_at: value.attributes.readable_at.clone(),
+        }
+    }
+}
+
+/// Models the settings for a guild as they appear in the database.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GuildSettings {
+    /// The id of the guild.
+    #[serde(rename = "_id")]
+    pub guild_id: GuildId,
+    /// The content ratings of the manga that may be tracked and announced in this guild.
+    pub content_ratings: Vec<ContentRating>,
+}
+
+impl GuildSettings {
+    /// Creates the default settings for a guild.
+    pub fn new(guild_id: GuildId) -> Self {
+        Self {
+            guild_id,
+            content_ratings: ContentRating::DEFAULT.to_vec(),
+        }
+    }
+
+    /// Gets the content ratings allowed in a channel in this guild.
+    ///
+    /// NSFW content ratings are only allowed in channels marked as NSFW.
+    pub fn allowed_content_ratings(&self, nsfw_channel: bool) -> Vec<ContentRating> {
+        self.content_ratings
+            .iter()
+            .copied()
+            .filter(|rating| nsfw_channel || !rating.is_nsfw())
+            .collect()
+    }
+}
+
+/// Result type for database operations.
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Whether an error was raised by one of the storage backends (as opposed to, for example,
+/// a request to discord or MangaDex).
+pub fn is_database_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.is::<mongodb::error::Error>()
+        || err.is::<bson::de::Error>()
+        || err.is::<bson::ser::Error>()
+        || err.is::<sqlx::Error>()
+        || err.is::<sqlx::migrate::MigrateError>()
+}
+
+/// Storage for the manga tracked by channels and users along with the settings of guilds.
+#[async_trait]
+pub trait Store: std::fmt::Debug + Send + Sync + 'static {
+    /// Reads the manga with a given id if it is tracked.
+    async fn read_manga(&self, manga_id: &str) -> Result<Option<Manga>>;
+
+    /// Reads every tracked manga.
+    async fn read_all_manga(&self) -> Result<Vec<Manga>>;
+
+    /// Reads every manga that a given subscriber is subscribed to.
+    async fn read_subscribed_manga(&self, subscriber: Subscriber) -> Result<Vec<Manga>>;
+
+    /// Starts tracking a new manga.
+    async fn create_manga(&self, manga: Manga) -> Result<()>;
+
+    /// Stops tracking a manga.
+    async fn delete_manga(&self, manga_id: &str) -> Result<()>;
+
+    /// Replaces the subscriptions to a manga.
+    async fn set_subscriptions(&self, manga_id: &str, subscriptions: &[Subscription])
+        -> Result<()>;
+
+    /// Records the guild of a channel subscribed to a manga, leaving the rest of the
+    /// subscription and the manga's other subscriptions as they are.
+    async fn set_subscription_guild(
+        &self,
+        manga_id: &str,
+        channel_id: ChannelId,
+        guild_id: GuildId,
+    ) -> Result<()>;
+
+    /// Sets the latest chapter of a manga that has been announced in a given language.
+    async fn set_latest_chapter(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+    ) -> Result<()>;
+
+    /// Reads the settings for a guild, returning the default settings if there are none.
+    async fn read_guild_settings(&self, guild_id: GuildId) -> Result<GuildSettings>;
+
+    /// Reads the settings for every guild that has any.
+    async fn read_all_guild_settings(&self) -> Result<Vec<GuildSettings>>;
+
+    /// Creates or replaces the settings for a guild.
+    async fn save_guild_settings(&self, settings: GuildSettings) -> Result<()>;
+
+    /// Checks that the database can be reached.
+    async fn ping(&self) -> Result<()>;
+
+    /// Adds notifications to the outbox, skipping any that are already in it, and sets the
+    /// latest chapter of a manga that has been announced in a given language.
+    ///
+    /// Both happen together, so a chapter is either queued for announcement and marked as
+    /// announced, or neither and it will be picked up again by the next scan.
+    async fn enqueue_notifications(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+        notifications: &[Notification],
+    ) -> Result<()>;
+
+    /// Reads the notifications waiting to be delivered in the order they were queued.
+    async fn read_pending_notifications(&self) -> Result<Vec<Notification>>;
+
+    /// Marks a pending notification as being delivered, recording another delivery attempt.
+    ///
+    /// Returns whether the notification was claimed, which it isn't if it's no longer
+    /// pending (e.g., because something else claimed it first).
+    async fn claim_notification(&self, id: &str, attempts: u32) -> Result<bool>;
+
+    /// Sets the status of a notification and the number of times delivery was attempted.
+    async fn set_notification_status(
+        &self,
+        id: &str,
+        status: NotificationStatus,
+        attempts: u32,
+    ) -> Result<()>;
+
+    /// Marks every notification that was being delivered as failed, returning how many there
+    /// were.
+    ///
+    /// Used when starting up since the previous run was interrupted while delivering them.
+    async fn fail_interrupted_notifications(&self) -> Result<u64>;
+
+    /// Deletes the sent and failed notifications queued before a given unix timestamp.
+    async fn prune_notifications(&self, before: i64) -> Result<()>;
+
+    /// Removes the subscriptions of the given subscribers from a manga.
+    ///
+    /// The manga is deleted entirely if no subscriptions remain as there's no reason to keep
+    /// tracking it.
+    #[tracing::instrument(err, skip(self, manga), fields(manga = manga.id))]
+    async fn remove_subscribers(&self, manga: &Manga, subscribers: &[Subscriber]) -> Result<()> {
+        let remaining: Vec<_> = manga
+            .subscribers
+            .iter()
+            .filter(|s| !subscribers.contains(&s.subscriber))
+            .cloned()
+            .collect();
+
+        if remaining.is_empty() {
+            self.delete_manga(&manga.id).await
+        } else {
+            self.set_subscriptions(&manga.id, &remaining).await
+        }
+    }
+
+    /// Removes a subscriber from every manga it is subscribed to.
+    ///
+    /// Manga left without any subscribers are deleted.
+    #[tracing::instrument(err, skip(self))]
+    async fn remove_subscriber(&self, subscriber: Subscriber) -> Result<()> {
+        for manga in self.read_subscribed_manga(subscriber).await? {
+            self.remove_subscribers(&manga, &[subscriber]).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the subscriptions of every channel in a guild.
+    ///
+    /// Manga left without any subscribers are deleted.
+    #[tracing::instrument(err, skip(self))]
+    async fn remove_guild(&self, guild_id: GuildId) -> Result<()> {
+        for manga in self.read_all_manga().await? {
+            let subscribers: Vec<_> = manga
+                .subscribers
+                .iter()
+                .filter(|s| s.guild_id == Some(guild_id))
+                .map(|s| s.subscriber)
+                .collect();
+
+            if !subscribers.is_empty() {
+                self.remove_subscribers(&manga, &subscribers).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+
+    use super::*;
+
+    #[test]
+    fn reads_legacy_manga() {
+        let doc = doc! {
+            "_id": "a96676e5-8ae2-425e-b549-7f15dd34a6d8",
+            "title": "Komi Can't Communicate",
+            "latest_chapter_id": "ch-1",
+            "latest_chapter_number": "1",
+            "latest_publish_at": "2023-01-01T00:00:00+00:00",
+            "channels": ["1234"],
+        };
+
+        let manga: Manga = bson::from_document(doc).unwrap();
+        let marker = &manga.latest_chapters[mangadex::DEFAULT_LANGUAGE];
+        assert_eq!(marker.id, "ch-1");
+        assert_eq!(marker.number.as_deref(), Some("1"));
+        assert_eq!(
+            marker.publish_at.as_deref(),
+            Some("2023-01-01T00:00:00+00:00")
+        );
+
+        // Written back in the current format without losing the marker.
+        let manga: Manga = bson::from_document(bson::to_document(&manga).unwrap()).unwrap();
+        assert_eq!(manga.latest_chapters[mangadex::DEFAULT_LANGUAGE].id, "ch-1");
+        assert_eq!(
+            manga.subscribers[0].subscriber,
+            Subscriber::Channel(ChannelId(1234))
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Storage backed by an Azure Cosmos MongoDB NoSQL database.
+
+use std::sync::Arc;
+
+use bson::{doc, Bson, Document};
+use mongodb::{
+    options::{ClientOptions, ReplaceOptions, UpdateOptions},
+    Client, Collection, Database,
+};
+use serenity::{
+    async_trait,
+    model::prelude::{ChannelId, GuildId},
+};
+
+use crate::mangadex;
+
+use super::{
+    ChapterMarker, GuildSettings, Manga, Notification, NotificationStatus, Result, Store,
+    Subscriber, Subscription,
+};
+
+impl From<GuildSettings> for Document {
+    fn from(value: GuildSettings) -> Self {
+        let value = bson::to_bson(&value).unwrap();
+        let doc = value.as_document().unwrap();
+        doc.clone()
+    }
+}
+
+impl TryFrom<Document> for GuildSettings {
+    type Error = bson::de::Error;
+
+    fn try_from(value: Document) -> std::result::Result<Self, Self::Error> {
+        bson::from_bson(Bson::Document(value))
+    }
+}
+
+impl From<Manga> for Document {
+    fn from(value: Manga) -> Self {
+        let value = bson::to_bson(&value).unwrap();
+        let doc = value.as_document().unwrap();
+        doc.clone()
+    }
+}
+
+impl TryFrom<Document> for Manga {
+    type Error = bson::de::Error;
+
+    fn try_from(value: Document) -> std::result::Result<Self, Self::Error> {
+        bson::from_bson(Bson::Document(value))
+    }
+}
+
+impl TryFrom<Document> for Notification {
+    type Error = bson::de::Error;
+
+    fn try_from(value: Document) -> std::result::Result<Self, Self::Error> {
+        bson::from_bson(Bson::Document(value))
+    }
+}
+
+/// Constructs a filter that matches all manga that a given subscriber is subscribed to.
+fn subscriber_filter(subscriber: Subscriber) -> Document {
+    match subscriber {
+        Subscriber::Channel(channel_id) => {
+            // Channels used to be stored as bare ids.
+            let channel_id = bson::to_bson(&channel_id).unwrap();
+            doc! {
+                "$or": [
+                    { "channels": &channel_id },
+                    { "channels.channel_id": &channel_id },
+                ]
+            }
+        }
+        Subscriber::User(user_id) => {
+            let user_id = bson::to_bson(&user_id).unwrap();
+            doc! { "channels.user_id": user_id }
+        }
+    }
+}
+
+/// Client that connects to a MongoDB server.
+#[derive(Debug)]
+pub struct MongoClient {
+    database: Database,
+    collection: Collection<Document>,
+    settings: Collection<Document>,
+    outbox: Collection<Document>,
+}
+
+impl MongoClient {
+    /// Connects to the mongo server using a given connection string.
+    ///
+    /// Manga are stored in `collection`, guild settings are stored in `settings` and
+    /// notifications waiting to be delivered are stored in `outbox`.
+    pub async fn connect(
+        connection_string: &str,
+        database: &str,
+        collection: &str,
+        settings: &str,
+        outbox: &str,
+    ) -> Result<Arc<Self>> {
+        let options = ClientOptions::parse(connection_string).await?;
+        let client = Client::with_options(options)?;
+        let database = client.database(database);
+        let collection = database.collection(collection);
+        let settings = database.collection(settings);
+        let outbox = database.collection(outbox);
+
+        Ok(Arc::new(Self {
+            database,
+            collection,
+            settings,
+            outbox,
+        }))
+    }
+
+    /// Reads multiple manga from the collection.
+    async fn read_many(&self, filter: Document) -> Result<Vec<Manga>> {
+        let mut results = Vec::new();
+
+        let mut cursor = self.collection.find(filter, None).await?;
+        while cursor.advance().await? {
+            let current = cursor.deserialize_current()?;
+            results.push(Manga::try_from(current)?);
+        }
+
+        Ok(results)
+    }
+
+    /// Applies a `$set` update to a manga in the collection.
+    async fn set(&self, manga_id: &str, update: Document) -> Result<()> {
+        self.collection
+            .update_one(doc! { "_id": manga_id }, doc! { "$set": update }, None)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.into())
+    }
+}
+
+#[async_trait]
+impl Store for MongoClient {
+    #[tracing::instrument(err, skip(self))]
+    async fn read_manga(&self, manga_id: &str) -> Result<Option<Manga>> {
+        match self
+            .collection
+            .find_one(Some(doc! { "_id": manga_id }), None)
+            .await?
+        {
+            Some(doc) => Ok(Some(Manga::try_from(doc)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_all_manga(&self) -> Result<Vec<Manga>> {
+        self.read_many(doc! {}).await
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_subscribed_manga(&self, subscriber: Subscriber) -> Result<Vec<Manga>> {
+        self.read_many(subscriber_filter(subscriber)).await
+    }
+
+    #[tracing::instrument(err, skip_all, fields(manga = manga.id))]
+    async fn create_manga(&self, manga: Manga) -> Result<()> {
+        self.collection
+            .insert_one(Document::from(manga), None)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.into())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn delete_manga(&self, manga_id: &str) -> Result<()> {
+        self.collection
+            .delete_one(doc! { "_id": manga_id }, None)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.into())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_subscriptions(
+        &self,
+        manga_id: &str,
+        subscriptions: &[Subscription],
+    ) -> Result<()> {
+        self.set(manga_id, doc! { "channels": bson::to_bson(subscriptions)? })
+            .await
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_subscription_guild(
+        &self,
+        manga_id: &str,
+        channel_id: ChannelId,
+        guild_id: GuildId,
+    ) -> Result<()> {
+        let channel = bson::to_bson(&channel_id)?;
+        self.collection
+            .update_one(
+                doc! { "_id": manga_id, "channels.channel_id": &channel },
+                doc! { "$set": { "channels.$.guild_id": bson::to_bson(&guild_id)? } },
+                None,
+            )
+            .await?;
+
+        // Channels used to be stored as bare ids, which have to be replaced with a whole
+        // subscription.
+        let subscription = Subscription {
+            subscriber: Subscriber::Channel(channel_id),
+            guild_id: Some(guild_id),
+            language: String::from(mangadex::DEFAULT_LANGUAGE),
+            role_id: None,
+        };
+        self.collection
+            .update_one(
+                doc! { "_id": manga_id, "channels": &channel },
+                doc! { "$set": { "channels.$": bson::to_bson(&subscription)? } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_latest_chapter(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+    ) -> Result<()> {
+        self.set(
+            manga_id,
+            doc! { format!("latest_chapters.{language}"): bson::to_bson(marker)? },
+        )
+        .await
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_guild_settings(&self, guild_id: GuildId) -> Result<GuildSettings> {
+        let filter = doc! { "_id": bson::to_bson(&guild_id)? };
+        match self.settings.find_one(Some(filter), None).await? {
+            Some(doc) => Ok(GuildSettings::try_from(doc)?),
+            None => Ok(GuildSettings::new(guild_id)),
+        }
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_all_guild_settings(&self) -> Result<Vec<GuildSettings>> {
+        let mut results = Vec::new();
+
+        let mut cursor = self.settings.find(doc! {}, None).await?;
+        while cursor.advance().await? {
+            let current = cursor.deserialize_current()?;
+            results.push(GuildSettings::try_from(current)?);
+        }
+
+        Ok(results)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn save_guild_settings(&self, settings: GuildSettings) -> Result<()> {
+        let filter = doc! { "_id": bson::to_bson(&settings.guild_id)? };
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.settings
+            .replace_one(filter, Document::from(settings), options)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.into())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn ping(&self) -> Result<()> {
+        self.database.run_command(doc! { "ping": 1 }, None).await?;
+        Ok(())
+    }
+
+    /// Notifications are inserted before the latest chapter is set since there's no
+    /// transaction spanning both collections. If setting it fails, the next scan queues the
+    /// same notifications again and they are skipped as duplicates.
+    #[tracing::instrument(err, skip(self, notifications))]
+    async fn enqueue_notifications(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+        notifications: &[Notification],
+    ) -> Result<()> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        for notification in notifications {
+            let mut doc = bson::to_document(notification)?;
+            doc.remove("_id");
+            self.outbox
+                .update_one(
+                    doc! { "_id": &notification.id },
+                    doc! { "$setOnInsert": doc },
+                    options.clone(),
+                )
+                .await?;
+        }
+
+        self.set_latest_chapter(manga_id, language, marker).await
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_pending_notifications(&self) -> Result<Vec<Notification>> {
+        let mut results = Vec::new();
+
+        let filter = doc! { "status": NotificationStatus::Pending.as_str() };
+        let mut cursor = self.outbox.find(filter, None).await?;
+        while cursor.advance().await? {
+            let current = cursor.deserialize_current()?;
+            results.push(Notification::try_from(current)?);
+        }
+
+        // Sorted here rather than by the server since Cosmos DB can only sort by fields that
+        // have been indexed.
+        results.sort_by_key(|n| (n.queued_at, n.position));
+        Ok(results)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn claim_notification(&self, id: &str, attempts: u32) -> Result<bool> {
+        let result = self
+            .outbox
+            .update_one(
+                doc! { "_id": id, "status": NotificationStatus::Pending.as_str() },
+                doc! {
+                    "$set": { "status": NotificationStatus::Sending.as_str(), "attempts": attempts },
+                },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_notification_status(
+        &self,
+        id: &str,
+        status: NotificationStatus,
+        attempts: u32,
+    ) -> Result<()> {
+        self.outbox
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "status": status.as_str(), "attempts": attempts } },
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(|err| err.into())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn fail_interrupted_notifications(&self) -> Result<u64> {
+        let result = self
+            .outbox
+            .update_many(
+                doc! { "status": NotificationStatus::Sending.as_str() },
+                doc! { "$set": { "status": NotificationStatus::Failed.as_str() } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn prune_notifications(&self, before: i64) -> Result<()> {
+        let filter = doc! {
+            "status": {
+                "$in": [NotificationStatus::Sent.as_str(), NotificationStatus::Failed.as_str()],
+            },
+            "queued_at": { "$lt": before },
+        };
+        self.outbox
+            .delete_many(filter, None)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.into())
+    }
+}
@@ -0,0 +1,866 @@
+//! Storage backed by a SQLite database.
+//!
+//! The schema is created and kept up to date by the migrations embedded from the
+//! `migrations` directory when connecting.
+
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use serenity::{
+    async_trait,
+    model::prelude::{ChannelId, GuildId, RoleId, UserId},
+};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePool},
+    SqliteConnection,
+};
+
+use crate::mangadex::ContentRating;
+
+use super::{
+    ChapterMarker, GuildSettings, Manga, Notification, NotificationStatus, Result, Store,
+    Subscriber, Subscription,
+};
+
+/// A row of the `manga` table.
+type MangaRow = (String, String);
+
+/// A row of the `latest_chapters` table.
+type ChapterRow = (String, String, String, Option<String>, Option<String>);
+
+/// A row of the `subscriptions` table.
+type SubscriptionRow = (
+    String,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    String,
+    Option<i64>,
+);
+
+/// A row of the `notifications` table.
+type NotificationRow = (
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    String,
+    String,
+    i64,
+    i64,
+    i64,
+);
+
+/// Client that connects to a SQLite database.
+#[derive(Debug)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Connects to the SQLite database at a given URL (e.g., `sqlite://mangadex-bot.db`),
+    /// creating it if it doesn't exist and running any pending migrations.
+    pub async fn connect(database_url: &str) -> Result<Arc<Self>> {
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .foreign_keys(true);
+        let pool = SqlitePool::connect_with(options).await?;
+        sqlx::migrate!().run(&pool).await?;
+
+        Ok(Arc::new(Self { pool }))
+    }
+}
+
+/// Splits a subscriber into its `channel_id` and `user_id` columns.
+fn subscriber_columns(subscriber: Subscriber) -> (Option<i64>, Option<i64>) {
+    match subscriber {
+        Subscriber::Channel(channel_id) => (Some(channel_id.0 as i64), None),
+        Subscriber::User(user_id) => (None, Some(user_id.0 as i64)),
+    }
+}
+
+/// Builds manga from the rows of the `manga`, `latest_chapters` and `subscriptions` tables.
+///
+/// Subscriptions with neither a channel nor a user are ignored.
+fn assemble(
+    manga: Vec<MangaRow>,
+    chapters: Vec<ChapterRow>,
+    subscriptions: Vec<SubscriptionRow>,
+) -> Vec<Manga> {
+    let mut manga: Vec<Manga> = manga
+        .into_iter()
+        .map(|(id, title)| Manga {
+            id,
+            title,
+            latest_chapters: HashMap::new(),
+            subscribers: Vec::new(),
+        })
+        .collect();
+    let index: HashMap<String, usize> = manga
+        .iter()
+        .enumerate()
+        .map(|(i, m)| (m.id.clone(), i))
+        .collect();
+
+    for (manga_id, language, id, number, publish_at) in chapters {
+        if let Some(&i) = index.get(&manga_id) {
+            let marker = ChapterMarker {
+                id,
+                number,
+                publish_at,
+            };
+            manga[i].latest_chapters.insert(language, marker);
+        }
+    }
+
+    for (manga_id, channel_id, user_id, guild_id, language, role_id) in subscriptions {
+        let subscriber = match (channel_id, user_id) {
+            (Some(channel_id), _) => Subscriber::Channel(ChannelId(channel_id as u64)),
+            (None, Some(user_id)) => Subscriber::User(UserId(user_id as u64)),
+            (None, None) => continue,
+        };
+
+        if let Some(&i) = index.get(&manga_id) {
+            manga[i].subscribers.push(Subscription {
+                subscriber,
+                guild_id: guild_id.map(|id| GuildId(id as u64)),
+                language,
+                role_id: role_id.map(|id| RoleId(id as u64)),
+            });
+        }
+    }
+
+    manga
+}
+
+/// Builds a notification from a row of the `notifications` table.
+///
+/// Rows with neither a channel nor a user, or with an unknown status, are ignored.
+fn notification_from_row(row: NotificationRow) -> Result<Option<Notification>> {
+    let (
+        id,
+        manga_id,
+        manga_title,
+        cover_url,
+        channel_id,
+        user_id,
+        role_id,
+        chapters,
+        status,
+        attempts,
+        queued_at,
+        position,
+    ) = row;
+
+    let subscriber = match (channel_id, user_id) {
+        (Some(channel_id), _) => Subscriber::Channel(ChannelId(channel_id as u64)),
+        (None, Some(user_id)) => Subscriber::User(UserId(user_id as u64)),
+        (None, None) => return Ok(None),
+    };
+    let Some(status) = parse_notification_status(&status) else {
+        return Ok(None);
+    };
+
+    Ok(Some(Notification {
+        id,
+        manga_id,
+        manga_title,
+        cover_url,
+        subscriber,
+        role_id: role_id.map(|id| RoleId(id as u64)),
+        chapters: serde_json::from_str(&chapters)?,
+        status,
+        attempts: attempts as u32,
+        queued_at,
+        position: position as u32,
+    }))
+}
+
+/// Inserts the subscriptions to a manga.
+async fn insert_subscriptions(
+    conn: &mut SqliteConnection,
+    manga_id: &str,
+    subscriptions: &[Subscription],
+) -> Result<()> {
+    for subscription in subscriptions {
+        let (channel_id, user_id) = subscriber_columns(subscription.subscriber);
+        sqlx::query(
+            "INSERT INTO subscriptions (manga_id, channel_id, user_id, guild_id, language, role_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(manga_id)
+        .bind(channel_id)
+        .bind(user_id)
+        .bind(subscription.guild_id.map(|id| id.0 as i64))
+        .bind(&subscription.language)
+        .bind(subscription.role_id.map(|id| id.0 as i64))
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Creates or replaces the latest chapter of a manga in a given language.
+///
+/// Nothing happens if the manga isn't tracked.
+async fn upsert_latest_chapter(
+    conn: &mut SqliteConnection,
+    manga_id: &str,
+    language: &str,
+    marker: &ChapterMarker,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO latest_chapters (manga_id, language, chapter_id, number, publish_at)
+        SELECT ?1, ?2, ?3, ?4, ?5 WHERE EXISTS (SELECT 1 FROM manga WHERE id = ?1)
+        ON CONFLICT (manga_id, language) DO UPDATE SET
+            chapter_id = excluded.chapter_id,
+            number = excluded.number,
+            publish_at = excluded.publish_at",
+    )
+    .bind(manga_id)
+    .bind(language)
+    .bind(&marker.id)
+    .bind(&marker.number)
+    .bind(&marker.publish_at)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    #[tracing::instrument(err, skip(self))]
+    async fn read_manga(&self, manga_id: &str) -> Result<Option<Manga>> {
+        let manga = sqlx::query_as("SELECT id, title FROM manga WHERE id = ?1")
+            .bind(manga_id)
+            .fetch_all(&self.pool)
+            .await?;
+        let chapters = sqlx::query_as(
+            "SELECT manga_id, language, chapter_id, number, publish_at FROM latest_chapters
+            WHERE manga_id = ?1",
+        )
+        .bind(manga_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let subscriptions = sqlx::query_as(
+            "SELECT manga_id, channel_id, user_id, guild_id, language, role_id FROM subscriptions
+            WHERE manga_id = ?1 ORDER BY id",
+        )
+        .bind(manga_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(assemble(manga, chapters, subscriptions).pop())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_all_manga(&self) -> Result<Vec<Manga>> {
+        let manga = sqlx::query_as("SELECT id, title FROM manga")
+            .fetch_all(&self.pool)
+            .await?;
+        let chapters = sqlx::query_as(
+            "SELECT manga_id, language, chapter_id, number, publish_at FROM latest_chapters",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let subscriptions = sqlx::query_as(
+            "SELECT manga_id, channel_id, user_id, guild_id, language, role_id FROM subscriptions
+            ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(assemble(manga, chapters, subscriptions))
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_subscribed_manga(&self, subscriber: Subscriber) -> Result<Vec<Manga>> {
+        let (channel_id, user_id) = subscriber_columns(subscriber);
+        let manga_ids: Vec<(String,)> = sqlx::query_as(
+            "SELECT manga_id FROM subscriptions WHERE channel_id IS ?1 AND user_id IS ?2",
+        )
+        .bind(channel_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut results = Vec::new();
+        for (manga_id,) in manga_ids {
+            if let Some(manga) = self.read_manga(&manga_id).await? {
+                results.push(manga);
+            }
+        }
+
+        Ok(results)
+    }
+
+    #[tracing::instrument(err, skip_all, fields(manga = manga.id))]
+    async fn create_manga(&self, manga: Manga) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("INSERT INTO manga (id, title) VALUES (?1, ?2)")
+            .bind(&manga.id)
+            .bind(&manga.title)
+            .execute(&mut *tx)
+            .await?;
+        for (language, marker) in manga.latest_chapters.iter() {
+            upsert_latest_chapter(&mut tx, &manga.id, language, marker).await?;
+        }
+        insert_subscriptions(&mut tx, &manga.id, &manga.subscribers).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn delete_manga(&self, manga_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM manga WHERE id = ?1")
+            .bind(manga_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_subscriptions(
+        &self,
+        manga_id: &str,
+        subscriptions: &[Subscription],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM subscriptions WHERE manga_id = ?1")
+            .bind(manga_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_subscriptions(&mut tx, manga_id, subscriptions).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_subscription_guild(
+        &self,
+        manga_id: &str,
+        channel_id: ChannelId,
+        guild_id: GuildId,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE subscriptions SET guild_id = ?3 WHERE manga_id = ?1 AND channel_id = ?2",
+        )
+        .bind(manga_id)
+        .bind(channel_id.0 as i64)
+        .bind(guild_id.0 as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_latest_chapter(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        upsert_latest_chapter(&mut conn, manga_id, language, marker).await
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_guild_settings(&self, guild_id: GuildId) -> Result<GuildSettings> {
+        let exists: Option<(i64,)> =
+            sqlx::query_as("SELECT guild_id FROM guild_settings WHERE guild_id = ?1")
+                .bind(guild_id.0 as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        if exists.is_none() {
+            return Ok(GuildSettings::new(guild_id));
+        }
+
+        let content_ratings: Vec<(String,)> = sqlx::query_as(
+            "SELECT content_rating FROM guild_content_ratings WHERE guild_id = ?1 ORDER BY rowid",
+        )
+        .bind(guild_id.0 as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(GuildSettings {
+            guild_id,
+            content_ratings: content_ratings
+                .iter()
+                .filter_map(|(rating,)| parse_content_rating(rating))
+                .collect(),
+        })
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_all_guild_settings(&self) -> Result<Vec<GuildSettings>> {
+        let guilds: Vec<(i64,)> = sqlx::query_as("SELECT guild_id FROM guild_settings")
+            .fetch_all(&self.pool)
+            .await?;
+        let content_ratings: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT guild_id, content_rating FROM guild_content_ratings ORDER BY rowid",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut settings: HashMap<i64, GuildSettings> = guilds
+            .into_iter()
+            .map(|(guild_id,)| {
+                let settings = GuildSettings {
+                    guild_id: GuildId(guild_id as u64),
+                    content_ratings: Vec::new(),
+                };
+                (guild_id, settings)
+            })
+            .collect();
+        for (guild_id, rating) in content_ratings {
+            if let (Some(settings), Some(rating)) =
+                (settings.get_mut(&guild_id), parse_content_rating(&rating))
+            {
+                settings.content_ratings.push(rating);
+            }
+        }
+
+        Ok(settings.into_values().collect())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn save_guild_settings(&self, settings: GuildSettings) -> Result<()> {
+        let guild_id = settings.guild_id.0 as i64;
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("INSERT OR IGNORE INTO guild_settings (guild_id) VALUES (?1)")
+            .bind(guild_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM guild_content_ratings WHERE guild_id = ?1")
+            .bind(guild_id)
+            .execute(&mut *tx)
+            .await?;
+        for rating in settings.content_ratings.iter() {
+            sqlx::query(
+                "INSERT OR IGNORE INTO guild_content_ratings (guild_id, content_rating)
+                VALUES (?1, ?2)",
+            )
+            .bind(guild_id)
+            .bind(rating.as_str())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self, notifications))]
+    async fn enqueue_notifications(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+        notifications: &[Notification],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for notification in notifications {
+            let (channel_id, user_id) = subscriber_columns(notification.subscriber);
+            sqlx::query(
+                "INSERT OR IGNORE INTO notifications (id, manga_id, manga_title, cover_url,
+                    channel_id, user_id, role_id, chapters, status, attempts, queued_at, position)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            )
+            .bind(&notification.id)
+            .bind(&notification.manga_id)
+            .bind(&notification.manga_title)
+            .bind(&notification.cover_url)
+            .bind(channel_id)
+            .bind(user_id)
+            .bind(notification.role_id.map(|id| id.0 as i64))
+            .bind(serde_json::to_string(&notification.chapters)?)
+            .bind(notification.status.as_str())
+            .bind(notification.attempts as i64)
+            .bind(notification.queued_at)
+            .bind(notification.position as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        upsert_latest_chapter(&mut tx, manga_id, language, marker).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn read_pending_notifications(&self) -> Result<Vec<Notification>> {
+        let rows: Vec<NotificationRow> = sqlx::query_as(
+            "SELECT id, manga_id, manga_title, cover_url, channel_id, user_id, role_id, chapters,
+                status, attempts, queued_at, position
+            FROM notifications WHERE status = ?1 ORDER BY queued_at, position",
+        )
+        .bind(NotificationStatus::Pending.as_str())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut notifications = Vec::new();
+        for row in rows {
+            notifications.extend(notification_from_row(row)?);
+        }
+
+        Ok(notifications)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn claim_notification(&self, id: &str, attempts: u32) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE notifications SET status = ?2, attempts = ?3 WHERE id = ?1 AND status = ?4",
+        )
+        .bind(id)
+        .bind(NotificationStatus::Sending.as_str())
+        .bind(attempts as i64)
+        .bind(NotificationStatus::Pending.as_str())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_notification_status(
+        &self,
+        id: &str,
+        status: NotificationStatus,
+        attempts: u32,
+    ) -> Result<()> {
+        sqlx::query("UPDATE notifications SET status = ?2, attempts = ?3 WHERE id = ?1")
+            .bind(id)
+            .bind(status.as_str())
+            .bind(attempts as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn fail_interrupted_notifications(&self) -> Result<u64> {
+        let result = sqlx::query("UPDATE notifications SET status = ?1 WHERE status = ?2")
+            .bind(NotificationStatus::Failed.as_str())
+            .bind(NotificationStatus::Sending.as_str())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn prune_notifications(&self, before: i64) -> Result<()> {
+        sqlx::query("DELETE FROM notifications WHERE status IN (?1, ?2) AND queued_at < ?3")
+            .bind(NotificationStatus::Sent.as_str())
+            .bind(NotificationStatus::Failed.as_str())
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Parses a content rating as stored in the database.
+fn parse_content_rating(value: &str) -> Option<ContentRating> {
+    ContentRating::ALL
+        .into_iter()
+        .find(|rating| rating.as_str() == value)
+}
+
+/// Parses a notification status as stored in the database.
+fn parse_notification_status(value: &str) -> Option<NotificationStatus> {
+    NotificationStatus::ALL
+        .into_iter()
+        .find(|status| status.as_str() == value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{AnnouncedChapter, Notification};
+
+    use super::*;
+
+    const MANGA_ID: &str = "a96676e5-8ae2-425e-b549-7f15dd34a6d8";
+
+    async fn store() -> Arc<SqliteStore> {
+        SqliteStore::connect("sqlite::memory:").await.unwrap()
+    }
+
+    fn subscription(subscriber: Subscriber, guild_id: Option<u64>) -> Subscription {
+        Subscription {
+            subscriber,
+            guild_id: guild_id.map(GuildId),
+            language: String::from("en"),
+            role_id: None,
+        }
+    }
+
+    fn manga(subscribers: Vec<Subscription>) -> Manga {
+        Manga {
+            id: String::from(MANGA_ID),
+            title: String::from("Komi Can't Communicate"),
+            latest_chapters: HashMap::new(),
+            subscribers,
+        }
+    }
+
+    fn notification(
+        manga: &Manga,
+        chapter_id: &str,
+        queued_at: i64,
+        position: u32,
+    ) -> Notification {
+        let chapter = AnnouncedChapter {
+            id: String::from(chapter_id),
+            number: Some(String::from("2")),
+            title: None,
+            volume: None,
+            pages: 20,
+            group: Some(String::from("Group")),
+            readable_at: None,
+        };
+
+        Notification::new(
+            manga,
+            &manga.subscribers[0],
+            vec![chapter],
+            Some(String::from(
+                "https://uploads.mangadex.org/covers/cover.jpg",
+            )),
+            queued_at,
+            position,
+        )
+    }
+
+    fn marker(chapter_id: &str) -> ChapterMarker {
+        ChapterMarker {
+            id: String::from(chapter_id),
+            number: Some(String::from("2")),
+            publish_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn subscriptions_round_trip() {
+        let db_client = store().await;
+        let mut channel = subscription(Subscriber::Channel(ChannelId(1234)), Some(1));
+        channel.role_id = Some(RoleId(42));
+        let legacy = subscription(Subscriber::Channel(ChannelId(5678)), None);
+        let user = subscription(Subscriber::User(UserId(9)), None);
+        db_client
+            .create_manga(manga(vec![channel.clone(), legacy.clone(), user.clone()]))
+            .await
+            .unwrap();
+
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(manga.subscribers, vec![channel, legacy, user.clone()]);
+
+        let subscribed = db_client
+            .read_subscribed_manga(Subscriber::User(UserId(9)))
+            .await
+            .unwrap();
+        assert_eq!(subscribed.len(), 1);
+
+        db_client
+            .set_subscriptions(MANGA_ID, std::slice::from_ref(&user))
+            .await
+            .unwrap();
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(manga.subscribers, vec![user]);
+    }
+
+    #[tokio::test]
+    async fn set_subscription_guild_keeps_other_subscriptions() {
+        let db_client = store().await;
+        let mut legacy = subscription(Subscriber::Channel(ChannelId(1234)), None);
+        legacy.role_id = Some(RoleId(42));
+        let other = subscription(Subscriber::Channel(ChannelId(5678)), None);
+        db_client
+            .create_manga(manga(vec![legacy.clone(), other.clone()]))
+            .await
+            .unwrap();
+
+        db_client
+            .set_subscription_guild(MANGA_ID, ChannelId(1234), GuildId(1))
+            .await
+            .unwrap();
+
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        legacy.guild_id = Some(GuildId(1));
+        assert_eq!(manga.subscribers, vec![legacy, other]);
+    }
+
+    #[tokio::test]
+    async fn remove_guild_keeps_other_subscriptions() {
+        let db_client = store().await;
+        db_client
+            .create_manga(manga(vec![
+                subscription(Subscriber::Channel(ChannelId(1234)), Some(1)),
+                subscription(Subscriber::Channel(ChannelId(5678)), Some(2)),
+                subscription(Subscriber::User(UserId(9)), None),
+            ]))
+            .await
+            .unwrap();
+
+        db_client.remove_guild(GuildId(1)).await.unwrap();
+
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        let subscribers: Vec<_> = manga.subscribers.iter().map(|s| s.subscriber).collect();
+        assert_eq!(
+            subscribers,
+            vec![
+                Subscriber::Channel(ChannelId(5678)),
+                Subscriber::User(UserId(9))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn enqueue_notifications_skips_duplicates() {
+        let db_client = store().await;
+        let manga = manga(vec![subscription(
+            Subscriber::Channel(ChannelId(1234)),
+            Some(1),
+        )]);
+        db_client.create_manga(manga.clone()).await.unwrap();
+
+        let notifications = [notification(&manga, "ch-2", 10, 0)];
+        for _ in 0..2 {
+            db_client
+                .enqueue_notifications(MANGA_ID, "en", &marker("ch-2"), &notifications)
+                .await
+                .unwrap();
+        }
+
+        let pending = db_client.read_pending_notifications().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, "channel-1234:ch-2");
+        assert_eq!(pending[0].chapters[0].group.as_deref(), Some("Group"));
+        assert_eq!(pending[0].status, NotificationStatus::Pending);
+
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(manga.latest_chapters["en"].id, "ch-2");
+    }
+
+    #[tokio::test]
+    async fn enqueue_notifications_is_atomic() {
+        let db_client = store().await;
+        let manga = manga(vec![subscription(
+            Subscriber::Channel(ChannelId(1234)),
+            Some(1),
+        )]);
+        db_client.create_manga(manga.clone()).await.unwrap();
+
+        // Make recording the latest chapter fail after the notifications are inserted.
+        sqlx::query(
+            "CREATE TRIGGER fail_latest_chapters BEFORE INSERT ON latest_chapters
+            BEGIN SELECT RAISE(ABORT, 'broken'); END",
+        )
+        .execute(&db_client.pool)
+        .await
+        .unwrap();
+
+        let result = db_client
+            .enqueue_notifications(
+                MANGA_ID,
+                "en",
+                &marker("ch-2"),
+                &[notification(&manga, "ch-2", 10, 0)],
+            )
+            .await;
+        assert!(result.is_err());
+        assert!(db_client
+            .read_pending_notifications()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn pending_notifications_are_read_in_order() {
+        let db_client = store().await;
+        let manga = manga(vec![subscription(
+            Subscriber::Channel(ChannelId(1234)),
+            Some(1),
+        )]);
+        db_client.create_manga(manga.clone()).await.unwrap();
+
+        let notifications = [
+            notification(&manga, "ch-4", 20, 0),
+            notification(&manga, "ch-3", 10, 1),
+            notification(&manga, "ch-2", 10, 0),
+        ];
+        db_client
+            .enqueue_notifications(MANGA_ID, "en", &marker("ch-4"), &notifications)
+            .await
+            .unwrap();
+
+        let pending = db_client.read_pending_notifications().await.unwrap();
+        let chapters: Vec<_> = pending.iter().map(|n| n.chapters[0].id.as_str()).collect();
+        assert_eq!(chapters, vec!["ch-2", "ch-3", "ch-4"]);
+    }
+
+    #[tokio::test]
+    async fn interrupted_and_old_notifications() {
+        let db_client = store().await;
+        let manga = manga(vec![subscription(
+            Subscriber::Channel(ChannelId(1234)),
+            Some(1),
+        )]);
+        db_client.create_manga(manga.clone()).await.unwrap();
+
+        let sent = notification(&manga, "ch-2", 10, 0);
+        let interrupted = notification(&manga, "ch-3", 10, 1);
+        let recent = notification(&manga, "ch-4", 30, 0);
+        db_client
+            .enqueue_notifications(
+                MANGA_ID,
+                "en",
+                &marker("ch-4"),
+                &[sent.clone(), interrupted.clone(), recent.clone()],
+            )
+            .await
+            .unwrap();
+
+        for notification in [&sent, &interrupted, &recent] {
+            assert!(db_client
+                .claim_notification(&notification.id, 1)
+                .await
+                .unwrap());
+        }
+        assert!(!db_client.claim_notification(&sent.id, 2).await.unwrap());
+        db_client
+            .set_notification_status(&sent.id, NotificationStatus::Sent, 1)
+            .await
+            .unwrap();
+        db_client
+            .set_notification_status(&recent.id, NotificationStatus::Sent, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(db_client.fail_interrupted_notifications().await.unwrap(), 1);
+        assert_eq!(db_client.fail_interrupted_notifications().await.unwrap(), 0);
+
+        // Only sent and failed notifications queued before the cutoff are deleted.
+        db_client.prune_notifications(20).await.unwrap();
+        let remaining: Vec<(String, String)> =
+            sqlx::query_as("SELECT id, status FROM notifications ORDER BY id")
+                .fetch_all(&db_client.pool)
+                .await
+                .unwrap();
+        assert_eq!(remaining, vec![(recent.id.clone(), String::from("sent"))]);
+    }
+}
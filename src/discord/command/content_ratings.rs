@@ -0,0 +1,124 @@
+//! The `content-ratings` command changes which content ratings may be tracked and announced in
+//! the guild that the command was invoked in.
+
+use std::sync::Arc;
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::{
+        prelude::{
+            command::CommandOptionType,
+            interaction::{
+                application_command::ApplicationCommandInteraction, InteractionResponseType,
+            },
+        },
+        Permissions,
+    },
+    prelude::Context,
+};
+
+use crate::{db::Store, mangadex::ContentRating};
+
+use super::{CommandError, SlashCommand};
+
+pub(super) struct ContentRatings<S> {
+    pub(super) db_client: Arc<S>,
+}
+
+#[async_trait]
+impl<S: Store> SlashCommand for ContentRatings<S> {
+    fn build<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("content-ratings")
+            .description("Choose which content ratings may be tracked in this server.")
+            .dm_permission(false)
+            .default_member_permissions(Permissions::MANAGE_GUILD);
+
+        for rating in ContentRating::ALL {
+            command.create_option(|option| {
+                option
+                    .name(rating.as_str())
+                    .description(format!("Allow manga rated {rating}."))
+                    .kind(CommandOptionType::Boolean)
+                    .required(false)
+            });
+        }
+
+        command
+    }
+
+    async fn run(
+        &self,
+        ctx: Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let options = command.data.options.as_slice();
+        tracing::info!(
+            command = command.data.name,
+            ?options,
+            "handling interaction"
+        );
+
+        let guild_id = command.guild_id.ok_or_else(|| {
+            tracing::error!(
+                command = command.data.name,
+                "command used outside of a guild"
+            );
+            CommandError::GuildOnly
+        })?;
+
+        // Apply any ratings which were specified, leaving the others as they were.
+        let mut settings = self.db_client.read_guild_settings(guild_id).await?;
+        for rating in ContentRating::ALL {
+            let allowed = options
+                .iter()
+                .find(|x| x.name == rating.as_str())
+                .and_then(|x| x.value.as_ref())
+                .and_then(|x| x.as_bool());
+
+            match allowed {
+                Some(true) if !settings.content_ratings.contains(&rating) => {
+                    settings.content_ratings.push(rating)
+                }
+                Some(false) => settings.content_ratings.retain(|r| *r != rating),
+                _ => {}
+            }
+        }
+
+        settings
+            .content_ratings
+            .sort_by_key(|r| ContentRating::ALL.iter().position(|x| x == r));
+
+        let ratings = settings
+            .content_ratings
+            .iter()
+            .map(|r| r.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut msg = match ratings.as_str() {
+            "" => String::from("No manga may be tracked in this server."),
+            _ => format!("Manga rated {ratings} may be tracked in this server."),
+        };
+        if settings.content_ratings.iter().any(|r| r.is_nsfw()) {
+            msg.push_str("\nErotica and pornographic manga are only announced in NSFW channels.");
+        }
+
+        if !options.is_empty() {
+            self.db_client.save_guild_settings(settings).await?;
+        }
+
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| message.content(msg).ephemeral(true))
+            })
+            .await?;
+
+        Ok(())
+    }
+}
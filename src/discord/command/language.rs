@@ -0,0 +1,170 @@
+//! The `language` command changes the translated language that chapter updates are announced
+//! in for the channel that the command was invoked in.
+
+use std::sync::Arc;
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::{
+        command::CommandOptionType, interaction::application_command::ApplicationCommandInteraction,
+    },
+    prelude::Context,
+};
+
+use crate::{
+    db::{ChapterMarker, Store, Subscriber},
+    mangadex::MangaDexClient,
+};
+
+use super::{
+    allowed_content_ratings, language_option, manga_id_from_option, url_or_id, CommandError,
+    SlashCommand,
+};
+
+pub(super) struct Language<S> {
+    pub(super) db_client: Arc<S>,
+    pub(super) mangadex: Arc<MangaDexClient>,
+}
+
+#[async_trait]
+impl<S: Store> SlashCommand for Language<S> {
+    fn build<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("language")
+            .description("Change the translated language that updates are announced in.")
+            .create_option(|option| {
+                option
+                    .name("language")
+                    .description("MangaDex language code (e.g., en, es-la or pt-br).")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+            })
+            .create_option(|option| {
+                option
+                    .name("url")
+                    .description("Manga URL or Id. Applies to all tracked manga if omitted.")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
+    }
+
+    fn deferred(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        ctx: Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let say = |msg: String| {
+            command.create_followup_message(&ctx.http, |message| message.content(msg))
+        };
+
+        let options = command.data.options.as_slice();
+        tracing::info!(
+            command = command.data.name,
+            ?options,
+            "handling interaction"
+        );
+
+        let language = language_option(options)?;
+        let content_ratings = allowed_content_ratings(
+            self.db_client.as_ref(),
+            &ctx.http,
+            command.guild_id,
+            command.channel_id,
+        )
+        .await?;
+
+        // Find the manga whose language should change, either a specific one or all of the
+        // manga tracked by this channel.
+        let channel_id = command.channel_id;
+        let subscriber = Subscriber::Channel(channel_id);
+        let url_or_id = url_or_id(options);
+        let mut manga = match url_or_id {
+            Some(url_or_id) => {
+                let manga_id = manga_id_from_option(url_or_id)
+                    .ok_or_else(|| {
+                        tracing::error!(
+                            command = command.data.name,
+                            ?options,
+                            "url or id option invalid"
+                        );
+                        CommandError::InvalidManga
+                    })?
+                    .to_string();
+
+                self.db_client
+                    .read_manga(&manga_id)
+                    .await?
+                    .filter(|manga| manga.subscription(subscriber).is_some())
+                    .into_iter()
+                    .collect()
+            }
+            None => self.db_client.read_subscribed_manga(subscriber).await?,
+        };
+
+        if manga.is_empty() {
+            let msg = match url_or_id {
+                Some(_) => "This manga is not tracked by this channel.",
+                None => "This channel is not tracking any manga.",
+            };
+            say(String::from(msg)).await?;
+            return Ok(());
+        }
+
+        for manga in manga.iter_mut() {
+            for subscription in manga.subscribers.iter_mut() {
+                if subscription.subscriber == subscriber {
+                    subscription.language = language.to_owned();
+                }
+            }
+
+            // Start tracking the latest chapter in the new language if no other channel
+            // already does.
+            let marker = if manga.latest_chapters.contains_key(language) {
+                None
+            } else {
+                self.mangadex
+                    .latest_chapter(&manga.id, language, &content_ratings)
+                    .await?
+                    .map(ChapterMarker::from)
+            };
+
+            self.db_client
+                .set_subscriptions(&manga.id, &manga.subscribers)
+                .await?;
+
+            if let Some(marker) = marker {
+                self.db_client
+                    .set_latest_chapter(&manga.id, language, &marker)
+                    .await?;
+            }
+        }
+
+        // And send a response back to the user.
+        match manga.as_slice() {
+            [manga] => {
+                say(format!(
+                    "Updates for {} will now be announced in {language}.",
+                    manga.title
+                ))
+                .await?;
+            }
+            _ => {
+                say(format!(
+                    "Updates for {} manga will now be announced in {language}.",
+                    manga.len()
+                ))
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! The `list` command shows all of the manga that are tracked by the channel that the
+//! command was invoked in.
+
+use std::sync::Arc;
+
+use serenity::{
+    async_trait,
+    builder::{CreateApplicationCommand, CreateComponents},
+    model::{
+        application::component::ButtonStyle,
+        prelude::{
+            interaction::{
+                application_command::ApplicationCommandInteraction,
+                message_component::MessageComponentInteraction, InteractionResponseType,
+            },
+            ChannelId,
+        },
+    },
+    prelude::Context,
+};
+
+use crate::db::{Manga, Store, Subscriber};
+use crate::mangadex;
+
+use super::{CommandError, SlashCommand};
+
+/// The maximum number of characters in a single discord message.
+const MESSAGE_LIMIT: usize = 2000;
+
+/// Number of characters reserved for the header line of each page.
+const HEADER_RESERVE: usize = 100;
+
+pub(super) struct List<S> {
+    pub(super) db_client: Arc<S>,
+}
+
+impl<S: Store> List<S> {
+    /// Renders the list of manga tracked by a channel into pages of message content.
+    async fn pages(
+        &self,
+        channel_id: ChannelId,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut manga = self
+            .db_client
+            .read_subscribed_manga(Subscriber::Channel(channel_id))
+            .await?;
+        manga.sort_by_key(|m| m.title.to_lowercase());
+
+        let mut pages = Vec::new();
+        let mut page = String::new();
+        for m in manga.iter() {
+            let line = list_entry(m, channel_id);
+            if page.len() + line.len() > MESSAGE_LIMIT - HEADER_RESERVE {
+                pages.push(std::mem::take(&mut page));
+            }
+
+            page.push_str(&line);
+        }
+
+        if !page.is_empty() {
+            pages.push(page);
+        }
+
+        // Prefix each page with a header now that the total number of pages is known.
+        let count = pages.len();
+        let pages = pages
+            .into_iter()
+            .enumerate()
+            .map(|(i, page)| {
+                let total = manga.len();
+                format!(
+                    "This channel is tracking {total} manga (page {} of {count}):\n{page}",
+                    i + 1
+                )
+            })
+            .collect();
+
+        Ok(pages)
+    }
+}
+
+#[async_trait]
+impl<S: Store> SlashCommand for List<S> {
+    fn build<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("list")
+            .description("List all manga tracked by this channel.")
+    }
+
+    async fn run(
+        &self,
+        ctx: Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        tracing::info!(command = command.data.name, "handling interaction");
+
+        let pages = self.pages(command.channel_id).await?;
+        command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| match pages.first() {
+                        Some(content) => message
+                            .content(content)
+                            .set_components(page_components(0, pages.len())),
+                        None => message.content("This channel is not tracking any manga."),
+                    })
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn run_component(
+        &self,
+        ctx: Context,
+        component: &MessageComponentInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let custom_id = component.data.custom_id.as_str();
+        tracing::info!(custom_id, "handling message component");
+
+        let page = custom_id
+            .strip_prefix("list:page:")
+            .and_then(|page| page.parse::<usize>().ok())
+            .ok_or_else(|| {
+                tracing::error!(custom_id, "invalid page component");
+                CommandError::ArgumentError
+            })?;
+
+        // The list may have changed since the message was sent so re-render it and clamp the
+        // requested page to the new page count.
+        let pages = self.pages(component.channel_id).await?;
+        let page = page.min(pages.len().saturating_sub(1));
+        component
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|message| match pages.get(page) {
+                        Some(content) => message
+                            .content(content)
+                            .set_components(page_components(page, pages.len())),
+                        None => message
+                            .content("This channel is not tracking any manga.")
+                            .set_components(CreateComponents::default()),
+                    })
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Formats a single line in the list for a given manga as seen by a given channel.
+fn list_entry(manga: &Manga, channel_id: ChannelId) -> String {
+    let title = manga.title.as_str();
+    let url = mangadex::manga_url(&manga.id);
+    let language = manga
+        .subscription(Subscriber::Channel(channel_id))
+        .map(|s| s.language.as_str())
+        .unwrap_or(mangadex::DEFAULT_LANGUAGE);
+
+    match manga.latest_chapters.get(language) {
+        Some(marker) => {
+            let chapter = match marker.number.as_deref() {
+                Some(number) => format!("ch. {number}"),
+                None => String::from("latest chapter"),
+            };
+            let chapter_url = mangadex::chapter_url(&marker.id);
+            format!("- [{title}](<{url}>) ({language}) - [{chapter}](<{chapter_url}>)\n")
+        }
+        None => format!("- [{title}](<{url}>) ({language}) - no chapters yet\n"),
+    }
+}
+
+/// Builds the previous/next buttons used to navigate between pages.
+///
+/// No buttons are created if there is only a single page.
+fn page_components(page: usize, count: usize) -> CreateComponents {
+    let mut components = CreateComponents::default();
+    if count <= 1 {
+        return components;
+    }
+
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(format!("list:page:{}", page.saturating_sub(1)))
+                .label("Previous")
+                .style(ButtonStyle::Secondary)
+                .disabled(page == 0)
+        })
+        .create_button(|button| {
+            button
+                .custom_id(format!("list:page:{}", page + 1))
+                .label("Next")
+                .style(ButtonStyle::Secondary)
+                .disabled(page + 1 >= count)
+        })
+    });
+
+    components
+}
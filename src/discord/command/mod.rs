@@ -3,32 +3,114 @@
 
 use std::{collections::HashMap, fmt::Display, sync::Arc};
 
+use bson::Uuid;
+use reqwest::Url;
 use serenity::{
-    async_trait, builder::CreateApplicationCommand,
-    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+    async_trait,
+    builder::CreateApplicationCommand,
+    http::Http,
+    model::prelude::{
+        interaction::{
+            application_command::{
+                ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+            },
+            autocomplete::AutocompleteInteraction,
+            message_component::MessageComponentInteraction,
+        },
+        ChannelId, GuildId, Permissions, Role,
+    },
     prelude::Context,
 };
+use url::Host;
 
-use crate::db::MongoClient;
+use crate::{
+    db::{self, ChapterMarker, Manga, Store, Subscription},
+    mangadex::{self, ContentRating, MangaDexClient},
+};
 
+mod content_ratings;
+mod language;
+mod list;
+mod notify_role;
+mod subscribe;
 mod track;
+mod unsubscribe;
+mod untrack;
+
+/// The maximum number of autocomplete choices that discord allows.
+const MAX_CHOICES: u32 = 25;
+
+/// The maximum length of an autocomplete choice name.
+const MAX_CHOICE_LENGTH: usize = 100;
 
 /// Error type returned by slash command handlers.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum CommandError {
+    /// An option, or the id of a message component, is missing or invalid.
     ArgumentError,
+    /// The url option is neither a manga id nor a MangaDex title URL.
+    InvalidManga,
+    /// The language option is not a MangaDex language code.
+    InvalidLanguage(String),
+    /// The command may only be used in a guild.
+    GuildOnly,
+    /// A role to mention was given by a member who isn't allowed to mention every role.
+    CannotMentionRoles,
 }
 
 impl Display for CommandError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             CommandError::ArgumentError => f.write_str("argument error"),
+            CommandError::InvalidManga => f.write_str("invalid manga url or id"),
+            CommandError::InvalidLanguage(language) => write!(f, "invalid language: {language}"),
+            CommandError::GuildOnly => f.write_str("command used outside of a guild"),
+            CommandError::CannotMentionRoles => f.write_str("member may not mention roles"),
         }
     }
 }
 
 impl std::error::Error for CommandError {}
 
+/// Builds the message shown to a user when handling their command fails with a given error.
+pub fn error_reply(err: &(dyn std::error::Error + 'static)) -> String {
+    if let Some(err) = err.downcast_ref::<CommandError>() {
+        return match err {
+            CommandError::ArgumentError => String::from("Some of the given options are invalid."),
+            CommandError::InvalidManga => {
+                String::from("That doesn't look like a MangaDex title URL or manga id.")
+            }
+            CommandError::InvalidLanguage(language) => {
+                format!("`{language}` isn't a MangaDex language code (e.g., en, es-la or pt-br).")
+            }
+            CommandError::GuildOnly => String::from("This command can only be used in a server."),
+            CommandError::CannotMentionRoles => String::from(
+                "You need the Mention All Roles permission to choose a role to mention.",
+            ),
+        };
+    }
+
+    if let Some(err) = err.downcast_ref::<mangadex::Error>() {
+        return match err {
+            mangadex::Error::NotFound(_) => {
+                String::from("There is no manga with that id on MangaDex.")
+            }
+            err if err.is_transient() => {
+                String::from("MangaDex is unavailable right now, please try again later.")
+            }
+            _ => String::from("MangaDex sent an unexpected response, please try again later."),
+        };
+    }
+
+    if db::is_database_error(err) {
+        return String::from(
+            "The bot's database is unavailable right now, please try again later.",
+        );
+    }
+
+    String::from("Something went wrong while handling this command.")
+}
+
 /// Core trait that all slash commands implement.
 #[async_trait]
 pub trait SlashCommand: Send + Sync {
@@ -38,12 +120,46 @@ pub trait SlashCommand: Send + Sync {
         command: &'a mut CreateApplicationCommand,
     ) -> &'a mut CreateApplicationCommand;
 
+    /// Whether the handler may take longer than discord allows for an initial response.
+    ///
+    /// If so, the interaction is acknowledged before the handler is run and the handler must
+    /// respond with a followup message instead.
+    fn deferred(&self) -> bool {
+        false
+    }
+
+    /// Whether the responses to this command are only visible to the user that invoked it.
+    fn ephemeral(&self) -> bool {
+        false
+    }
+
     /// The handler for the command.
     async fn run(
         &self,
         ctx: Context,
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// The handler for autocomplete requests for this command's options.
+    async fn autocomplete(
+        &self,
+        _ctx: Context,
+        _autocomplete: &AutocompleteInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
+
+    /// The handler for message components (e.g., buttons) attached to this command's responses.
+    ///
+    /// Components are routed to a command by prefixing their custom id with the name of the
+    /// command followed by a `:` (see [component_command_name]).
+    async fn run_component(
+        &self,
+        _ctx: Context,
+        _component: &MessageComponentInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
 }
 
 /// A mapping of command names to their implementations.
@@ -51,15 +167,346 @@ pub type SlashCommandMap = HashMap<String, Box<dyn SlashCommand>>;
 
 /// Initializes the set of slash commands for this bot.
 #[tracing::instrument]
-pub(crate) fn init(args: &crate::Args, db_client: Arc<MongoClient>) -> SlashCommandMap {
+pub(crate) fn init<S: Store>(
+    args: &crate::Args,
+    db_client: Arc<S>,
+    mangadex: Arc<MangaDexClient>,
+) -> SlashCommandMap {
     let mut commands: SlashCommandMap = HashMap::new();
 
     commands.insert(
         String::from("track"),
         Box::new(track::Track {
-            db_client,
+            db_client: db_client.clone(),
+            mangadex: mangadex.clone(),
         }),
     );
 
+    commands.insert(
+        String::from("content-ratings"),
+        Box::new(content_ratings::ContentRatings {
+            db_client: db_client.clone(),
+        }),
+    );
+
+    commands.insert(
+        String::from("language"),
+        Box::new(language::Language {
+            db_client: db_client.clone(),
+            mangadex: mangadex.clone(),
+        }),
+    );
+
+    commands.insert(
+        String::from("list"),
+        Box::new(list::List {
+            db_client: db_client.clone(),
+        }),
+    );
+
+    commands.insert(
+        String::from("notify-role"),
+        Box::new(notify_role::NotifyRole {
+            db_client: db_client.clone(),
+        }),
+    );
+
+    commands.insert(
+        String::from("subscribe"),
+        Box::new(subscribe::Subscribe {
+            db_client: db_client.clone(),
+            mangadex,
+        }),
+    );
+
+    commands.insert(
+        String::from("unsubscribe"),
+        Box::new(unsubscribe::Unsubscribe {
+            db_client: db_client.clone(),
+        }),
+    );
+
+    commands.insert(
+        String::from("untrack"),
+        Box::new(untrack::Untrack { db_client }),
+    );
+
     commands
 }
+
+/// Gets the content ratings allowed in a channel based on the settings of the guild it
+/// belongs to and whether it is marked as NSFW.
+async fn allowed_content_ratings(
+    db_client: &impl Store,
+    http: &Http,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+) -> Result<Vec<ContentRating>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(guild_id) = guild_id else {
+        return Ok(ContentRating::DEFAULT.to_vec());
+    };
+
+    let settings = db_client.read_guild_settings(guild_id).await?;
+
+    // Only bother looking up the channel if it could make a difference.
+    let nsfw = settings.content_ratings.iter().any(|r| r.is_nsfw())
+        && channel_id.to_channel(http).await?.is_nsfw();
+
+    Ok(settings.allowed_content_ratings(nsfw))
+}
+
+/// Adds a subscription to a given manga, creating a record for the manga if it isn't tracked
+/// yet, and returns the manga's title.
+///
+/// Nothing is changed and [None] is returned if the subscriber is already subscribed to the
+/// manga. The latest chapter in the subscription's language is fetched, provided it has one
+/// of the given content ratings, if no other subscriber follows the manga in that language.
+async fn add_subscription(
+    db_client: &impl Store,
+    mangadex: &MangaDexClient,
+    details: &mangadex::Manga,
+    subscription: Subscription,
+    content_ratings: &[ContentRating],
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let manga_id = details.id.as_str();
+    let language = subscription.language.clone();
+
+    // Check if this manga already has a record in the database.
+    if let Some(mut manga) = db_client.read_manga(manga_id).await? {
+        // If the manga is already followed by this subscriber, then there's nothing to do.
+        if manga.subscription(subscription.subscriber).is_some() {
+            tracing::info!(?subscription, %manga_id, "already subscribed to this manga");
+            return Ok(None);
+        }
+
+        // If no one else follows this manga in the same language, then we don't know what the
+        // latest chapter in that language is yet.
+        let marker = if manga.latest_chapters.contains_key(&language) {
+            None
+        } else {
+            mangadex
+                .latest_chapter(manga_id, &language, content_ratings)
+                .await?
+                .map(ChapterMarker::from)
+        };
+
+        // Then add this subscriber to the list.
+        manga.subscribers.push(subscription);
+        db_client
+            .set_subscriptions(manga_id, &manga.subscribers)
+            .await?;
+
+        if let Some(marker) = marker {
+            db_client
+                .set_latest_chapter(manga_id, &language, &marker)
+                .await?;
+        }
+
+        Ok(Some(manga.title))
+    } else {
+        // Otherwise, the manga does not already exist in the database so we need to insert it.
+        let title = details
+            .attributes
+            .english_title()
+            .map(|s| s.to_owned())
+            .unwrap_or_else(|| manga_id.to_owned());
+
+        let latest_chapters = mangadex
+            .latest_chapter(manga_id, &language, content_ratings)
+            .await?
+            .map(|chapter| (language.clone(), ChapterMarker::from(chapter)))
+            .into_iter()
+            .collect();
+
+        let manga = Manga {
+            id: manga_id.to_owned(),
+            title: title.clone(),
+            latest_chapters,
+            subscribers: vec![subscription],
+        };
+
+        db_client.create_manga(manga).await?;
+
+        Ok(Some(title))
+    }
+}
+
+/// Responds to an autocomplete request for a manga option with manga, having one of the given
+/// content ratings, whose title matches what the user has typed so far.
+async fn autocomplete_manga(
+    ctx: &Context,
+    mangadex: &MangaDexClient,
+    autocomplete: &AutocompleteInteraction,
+    content_ratings: &[ContentRating],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let query = autocomplete
+        .data
+        .options
+        .iter()
+        .find(|option| option.focused)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+        .unwrap_or_default()
+        .trim();
+
+    // Only search when the user has typed a title. URLs and ids are handled as-is.
+    let results = if query.is_empty() || manga_id_from_option(query).is_some() {
+        Vec::new()
+    } else {
+        mangadex
+            .search_manga(query, content_ratings, MAX_CHOICES)
+            .await?
+    };
+
+    autocomplete
+        .create_autocomplete_response(&ctx.http, |response| {
+            for manga in results.iter() {
+                let title = manga.attributes.english_title().unwrap_or(&manga.id);
+                response.add_string_choice(truncate(title, MAX_CHOICE_LENGTH), &manga.id);
+            }
+
+            response
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Truncates a string to at most `max` characters, adding an ellipsis if anything was removed.
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        s.to_owned()
+    } else {
+        let mut truncated: String = s.chars().take(max - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// Gets the name of the command that a message component belongs to from its custom id.
+pub fn component_command_name(custom_id: &str) -> &str {
+    custom_id.split(':').next().unwrap_or_default()
+}
+
+/// Gets the value of a string option with a given name from the list of options.
+fn option_str<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|x| x.name == name)
+        .and_then(|x| x.value.as_ref())
+        .and_then(|x| x.as_str())
+}
+
+/// Gets the role for a role option with a given name from the list of options.
+fn option_role<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a Role> {
+    options
+        .iter()
+        .find(|x| x.name == name)
+        .and_then(|x| x.resolved.as_ref())
+        .and_then(|x| match x {
+            CommandDataOptionValue::Role(role) => Some(role),
+            _ => None,
+        })
+}
+
+/// Gets a role to mention from the list of options, if given.
+///
+/// Announcements mention the role even if it isn't mentionable, so only members who may
+/// mention every role are allowed to choose one.
+fn option_mentioned_role<'a>(
+    command: &'a ApplicationCommandInteraction,
+    name: &str,
+) -> Result<Option<&'a Role>, CommandError> {
+    let Some(role) = option_role(&command.data.options, name) else {
+        return Ok(None);
+    };
+
+    let permissions = command
+        .member
+        .as_ref()
+        .and_then(|member| member.permissions);
+    if permissions.map_or(false, |p| p.contains(Permissions::MENTION_EVERYONE)) {
+        Ok(Some(role))
+    } else {
+        tracing::info!(role_id = %role.id, user_id = %command.user.id, "member may not mention roles");
+        Err(CommandError::CannotMentionRoles)
+    }
+}
+
+/// Gets the url or id option from the list of options.
+fn url_or_id(options: &[CommandDataOption]) -> Option<&str> {
+    option_str(options, "url")
+}
+
+/// Gets the language option from the list of options, falling back to the default language
+/// if it was not given.
+fn language_option(options: &[CommandDataOption]) -> Result<&str, CommandError> {
+    match option_str(options, "language") {
+        Some(language) if crate::mangadex::is_language_code(language) => Ok(language),
+        Some(language) => {
+            tracing::error!(language, "invalid language option");
+            Err(CommandError::InvalidLanguage(language.to_owned()))
+        }
+        None => Ok(crate::mangadex::DEFAULT_LANGUAGE),
+    }
+}
+
+/// Extracts the manga id from a command options that is either an id or URL.
+fn manga_id_from_option(url_or_id: &str) -> Option<Uuid> {
+    if let Ok(id) = Uuid::parse_str(url_or_id) {
+        Some(id)
+    } else if let Ok(url) = Url::parse(url_or_id) {
+        manga_id_from_url(url)
+    } else {
+        None
+    }
+}
+
+/// Parses a Mangadex URL to a specific manga extracting the manga id.
+fn manga_id_from_url(url: Url) -> Option<Uuid> {
+    if Some(Host::Domain("mangadex.org")) != url.host() {
+        return None;
+    }
+
+    let mut path_segments = url.path_segments()?;
+    if "title" != path_segments.next()? {
+        return None;
+    }
+
+    let id_str = path_segments.next()?;
+    Uuid::parse_str(id_str).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_reply_for_invalid_language() {
+        let err: Box<dyn std::error::Error + Send + Sync> =
+            CommandError::InvalidLanguage(String::from("english")).into();
+        assert_eq!(
+            error_reply(err.as_ref()),
+            "`english` isn't a MangaDex language code (e.g., en, es-la or pt-br)."
+        );
+    }
+
+    #[test]
+    fn error_reply_for_unavailable_mangadex() {
+        let err: Box<dyn std::error::Error + Send + Sync> =
+            mangadex::Error::RateLimited(Vec::new()).into();
+        assert_eq!(
+            error_reply(err.as_ref()),
+            "MangaDex is unavailable right now, please try again later."
+        );
+    }
+
+    #[test]
+    fn error_reply_for_database_error() {
+        let err: Box<dyn std::error::Error + Send + Sync> = sqlx::Error::PoolTimedOut.into();
+        assert_eq!(
+            error_reply(err.as_ref()),
+            "The bot's database is unavailable right now, please try again later."
+        );
+    }
+}
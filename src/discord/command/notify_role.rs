@@ -0,0 +1,128 @@
+//! The `notify-role` command changes the role that is mentioned when chapter updates for a
+//! specific manga are announced in the channel that the command was invoked in.
+
+use std::sync::Arc;
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::{
+        command::CommandOptionType,
+        interaction::{
+            application_command::ApplicationCommandInteraction, InteractionResponseType,
+        },
+        Permissions,
+    },
+    prelude::Context,
+};
+
+use crate::db::{Store, Subscriber};
+
+use super::{manga_id_from_option, option_mentioned_role, url_or_id, CommandError, SlashCommand};
+
+pub(super) struct NotifyRole<S> {
+    pub(super) db_client: Arc<S>,
+}
+
+#[async_trait]
+impl<S: Store> SlashCommand for NotifyRole<S> {
+    fn build<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("notify-role")
+            .description("Change the role that is mentioned when a manga is updated.")
+            .dm_permission(false)
+            .default_member_permissions(Permissions::MENTION_EVERYONE)
+            .create_option(|option| {
+                option
+                    .name("url")
+                    .description("Manga URL or Id.")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+            })
+            .create_option(|option| {
+                option
+                    .name("role")
+                    .description("Role to mention. No role is mentioned if omitted.")
+                    .kind(CommandOptionType::Role)
+                    .required(false)
+            })
+    }
+
+    async fn run(
+        &self,
+        ctx: Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let say = |msg: String| {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| message.content(msg))
+            })
+        };
+
+        let options = command.data.options.as_slice();
+        tracing::info!(
+            command = command.data.name,
+            ?options,
+            "handling interaction"
+        );
+
+        // Extract the manga id from the command arguments.
+        let manga_id = url_or_id(options)
+            .and_then(manga_id_from_option)
+            .ok_or_else(|| {
+                tracing::error!(
+                    command = command.data.name,
+                    ?options,
+                    "url or id option missing or invalid"
+                );
+                CommandError::InvalidManga
+            })?
+            .to_string();
+
+        let role = option_mentioned_role(command, "role")?;
+
+        let subscriber = Subscriber::Channel(command.channel_id);
+        let manga = self
+            .db_client
+            .read_manga(&manga_id)
+            .await?
+            .filter(|manga| manga.subscription(subscriber).is_some());
+
+        let Some(mut manga) = manga else {
+            tracing::info!(?subscriber, %manga_id, "channel does not track this manga");
+            say(String::from("This manga is not tracked by this channel.")).await?;
+            return Ok(());
+        };
+
+        for subscription in manga.subscribers.iter_mut() {
+            if subscription.subscriber == subscriber {
+                subscription.role_id = role.map(|role| role.id);
+            }
+        }
+
+        self.db_client
+            .set_subscriptions(&manga_id, &manga.subscribers)
+            .await?;
+
+        // And send a response back to the user. The role is named rather than mentioned so
+        // that its members don't get pinged by this response.
+        let title = manga.title.as_str();
+        match role {
+            Some(role) => say(format!(
+                "Updates for {title} will now mention @{}.",
+                role.name
+            )),
+            None => say(format!(
+                "Updates for {title} will no longer mention a role."
+            )),
+        }
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+//! The `subscribe` command tells the application to send updates for a specific manga to the
+//! user that invoked the command as direct messages.
+
+use std::sync::Arc;
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::{
+        command::CommandOptionType,
+        interaction::{
+            application_command::ApplicationCommandInteraction,
+            autocomplete::AutocompleteInteraction,
+        },
+    },
+    prelude::Context,
+};
+
+use crate::{
+    db::{Store, Subscriber, Subscription},
+    discord::{error_code, CANNOT_MESSAGE_USER},
+    mangadex::{self, ContentRating, MangaDexClient},
+};
+
+use super::{
+    add_subscription, autocomplete_manga, language_option, manga_id_from_option, url_or_id,
+    CommandError, SlashCommand,
+};
+
+pub(super) struct Subscribe<S> {
+    pub(super) db_client: Arc<S>,
+    pub(super) mangadex: Arc<MangaDexClient>,
+}
+
+#[async_trait]
+impl<S: Store> SlashCommand for Subscribe<S> {
+    fn build<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("subscribe")
+            .description("Receive updates for a given manga as direct messages.")
+            .create_option(|option| {
+                option
+                    .name("url")
+                    .description("Manga URL, Id or title.")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+                    .set_autocomplete(true)
+            })
+            .create_option(|option| {
+                option
+                    .name("language")
+                    .description("Translated language to receive chapters in (default: en).")
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
+    }
+
+    async fn autocomplete(
+        &self,
+        ctx: Context,
+        autocomplete: &AutocompleteInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        autocomplete_manga(&ctx, &self.mangadex, autocomplete, &ContentRating::DEFAULT).await
+    }
+
+    fn deferred(&self) -> bool {
+        true
+    }
+
+    // Responses are only visible to the user since subscriptions are personal.
+    fn ephemeral(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        ctx: Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let say = |msg: String| {
+            command
+                .create_followup_message(&ctx.http, |message| message.content(msg).ephemeral(true))
+        };
+
+        let options = command.data.options.as_slice();
+        tracing::info!(
+            command = command.data.name,
+            ?options,
+            "handling interaction"
+        );
+
+        // Extract the manga id from the command arguments.
+        let manga_id = url_or_id(options)
+            .and_then(manga_id_from_option)
+            .ok_or_else(|| {
+                tracing::error!(
+                    command = command.data.name,
+                    ?options,
+                    "url or id option missing or invalid"
+                );
+                CommandError::InvalidManga
+            })?
+            .to_string();
+
+        let language = language_option(options)?;
+        let user = &command.user;
+        let subscriber = Subscriber::User(user.id);
+        let subscription = Subscription {
+            subscriber,
+            guild_id: None,
+            language: language.to_owned(),
+            role_id: None,
+        };
+
+        // Direct messages aren't tied to any guild's settings so only the default content
+        // ratings are allowed.
+        let content_ratings = ContentRating::DEFAULT;
+        let details = match self.mangadex.manga(&manga_id).await {
+            Ok(details) => details,
+            Err(mangadex::Error::NotFound(_)) => {
+                tracing::info!(%manga_id, "manga does not exist");
+                say(String::from("There is no manga with that id on MangaDex.")).await?;
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(rating) = details.attributes.content_rating {
+            if !content_ratings.contains(&rating) {
+                tracing::info!(%manga_id, %rating, "content rating not allowed in direct messages");
+                say(format!("Manga rated {rating} may not be subscribed to.")).await?;
+                return Ok(());
+            }
+        }
+
+        let Some(title) = add_subscription(
+            self.db_client.as_ref(),
+            &self.mangadex,
+            &details,
+            subscription,
+            &content_ratings,
+        )
+        .await?
+        else {
+            say(String::from("You are already subscribed to this manga.")).await?;
+            return Ok(());
+        };
+
+        // Make sure updates can actually be delivered, undoing the subscription if they can't.
+        let confirmation = user
+            .direct_message(&ctx.http, |message| {
+                message.content(format!("Updates for {title} will be sent here."))
+            })
+            .await;
+
+        match confirmation {
+            Ok(_) => {
+                say(format!("Subscribed to {title}.")).await?;
+            }
+            Err(err) if error_code(&err) == Some(CANNOT_MESSAGE_USER) => {
+                tracing::info!(?subscriber, %manga_id, "user does not accept direct messages");
+                if let Some(manga) = self.db_client.read_manga(&manga_id).await? {
+                    self.db_client
+                        .remove_subscribers(&manga, &[subscriber])
+                        .await?;
+                }
+
+                say(String::from(
+                    "I can't send you direct messages. Allow direct messages from this server and try again.",
+                ))
+                .await?;
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(())
+    }
+}
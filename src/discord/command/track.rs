@@ -3,32 +3,36 @@
 
 use std::sync::Arc;
 
-use bson::{doc, Uuid};
-use reqwest::Url;
 use serenity::{
     async_trait,
     builder::CreateApplicationCommand,
     model::prelude::{
         command::CommandOptionType,
         interaction::{
-            application_command::{ApplicationCommandInteraction, CommandDataOption},
-            InteractionResponseType,
+            application_command::ApplicationCommandInteraction,
+            autocomplete::AutocompleteInteraction,
         },
     },
     prelude::Context,
 };
-use url::Host;
 
-use crate::db::MongoClient;
+use crate::{
+    db::{Store, Subscriber, Subscription},
+    mangadex::{self, MangaDexClient},
+};
 
-use super::{CommandError, SlashCommand};
+use super::{
+    add_subscription, allowed_content_ratings, autocomplete_manga, language_option,
+    manga_id_from_option, option_mentioned_role, url_or_id, CommandError, SlashCommand,
+};
 
-pub(super) struct Track {
-    pub(super) db_client: Arc<MongoClient>,
+pub(super) struct Track<S> {
+    pub(super) db_client: Arc<S>,
+    pub(super) mangadex: Arc<MangaDexClient>,
 }
 
 #[async_trait]
-impl SlashCommand for Track {
+impl<S: Store> SlashCommand for Track<S> {
     fn build<'a>(
         &self,
         command: &'a mut CreateApplicationCommand,
@@ -39,10 +43,47 @@ impl SlashCommand for Track {
             .create_option(|option| {
                 option
                     .name("url")
-                    .description("Manga URL or Id.")
+                    .description("Manga URL, Id or title.")
                     .kind(CommandOptionType::String)
                     .required(true)
+                    .set_autocomplete(true)
+            })
+            .create_option(|option| {
+                option
+                    .name("language")
+                    .description("Translated language to announce chapters in (default: en).")
+                    .kind(CommandOptionType::String)
+                    .required(false)
             })
+            .create_option(|option| {
+                option
+                    .name("role")
+                    .description(
+                        "Role to mention when announcing chapters. Requires Mention All Roles.",
+                    )
+                    .kind(CommandOptionType::Role)
+                    .required(false)
+            })
+    }
+
+    async fn autocomplete(
+        &self,
+        ctx: Context,
+        autocomplete: &AutocompleteInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let content_ratings = allowed_content_ratings(
+            self.db_client.as_ref(),
+            &ctx.http,
+            autocomplete.guild_id,
+            autocomplete.channel_id,
+        )
+        .await?;
+
+        autocomplete_manga(&ctx, &self.mangadex, autocomplete, &content_ratings).await
+    }
+
+    fn deferred(&self) -> bool {
+        true
     }
 
     async fn run(
@@ -51,11 +92,7 @@ impl SlashCommand for Track {
         command: &ApplicationCommandInteraction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let say = |msg: String| {
-            command.create_interaction_response(&ctx.http, |response| {
-                response
-                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|message| message.content(msg))
-            })
+            command.create_followup_message(&ctx.http, |message| message.content(msg))
         };
 
         let options = command.data.options.as_slice();
@@ -74,93 +111,67 @@ impl SlashCommand for Track {
                     ?options,
                     "url or id option missing or invalid"
                 );
-                CommandError::ArgumentError
+                CommandError::InvalidManga
             })?
             .to_string();
 
-        // Check if this manga already has a record in the database.
-        let channel_id = command.channel_id;
-        if let Some(mut manga) = self
-            .db_client
-            .read::<crate::db::Manga>(doc! { "_id": &manga_id })
-            .await?
-        {
-            // If the manga is already tracked by this channel, then there's nothing left to do.
-            if manga.channels.contains(&channel_id) {
-                tracing::info!(?channel_id, %manga_id, "channel already tracks this manga");
-                say(String::from(
-                    "This manga is already tracked by this channel.",
+        let language = language_option(options)?;
+        let subscription = Subscription {
+            subscriber: Subscriber::Channel(command.channel_id),
+            guild_id: command.guild_id,
+            language: language.to_owned(),
+            role_id: option_mentioned_role(command, "role")?.map(|role| role.id),
+        };
+
+        // Make sure that manga with this content rating may be tracked in this channel.
+        let content_ratings = allowed_content_ratings(
+            self.db_client.as_ref(),
+            &ctx.http,
+            command.guild_id,
+            command.channel_id,
+        )
+        .await?;
+        let details = match self.mangadex.manga(&manga_id).await {
+            Ok(details) => details,
+            Err(mangadex::Error::NotFound(_)) => {
+                tracing::info!(%manga_id, "manga does not exist");
+                say(String::from("There is no manga with that id on MangaDex.")).await?;
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(rating) = details.attributes.content_rating {
+            if !content_ratings.contains(&rating) {
+                tracing::info!(%manga_id, %rating, "content rating not allowed in channel");
+                say(format!(
+                    "Manga rated {rating} may not be tracked in this channel."
                 ))
                 .await?;
                 return Ok(());
             }
+        }
 
-            // Otherwise, add this channel to the list of manga.
-            let title = manga.title.clone();
-            manga.channels.push(channel_id);
-            self.db_client
-                .update(doc! { "_id": &manga_id }, manga)
+        // And send a response back to the user.
+        match add_subscription(
+            self.db_client.as_ref(),
+            &self.mangadex,
+            &details,
+            subscription,
+            &content_ratings,
+        )
+        .await?
+        {
+            Some(title) => {
+                say(format!("Now tracking {title}.")).await?;
+            }
+            None => {
+                say(String::from(
+                    "This manga is already tracked by this channel.",
+                ))
                 .await?;
-
-            // And send a response back to the user.
-            say(format!("Now tracking {title}.")).await?;
-        } else {
-            // Otherwise, the manga does not already exist in the database so we need to insert it.
-            let title = crate::mangadex::english_title(&manga_id)
-                .await?
-                .unwrap_or_else(|| manga_id.clone());
-
-            let latest_chapter_id = crate::mangadex::latest_chapter(&manga_id)
-                .await?
-                .map(|c| c.id);
-
-            let manga = crate::db::Manga {
-                id: manga_id.clone(),
-                title: title.clone(),
-                latest_chapter_id,
-                channels: vec![channel_id],
-            };
-
-            self.db_client.create(manga).await?;
-
-            // And send a response back to the user.
-            say(format!("Now tracking {title}.")).await?;
+            }
         }
 
         Ok(())
     }
 }
-
-/// Gets the url or id option from the list of options.
-fn url_or_id(options: &[CommandDataOption]) -> Option<&str> {
-    options
-        .first()
-        .and_then(|x| x.value.as_ref())
-        .and_then(|x| x.as_str())
-}
-
-/// Extracts the manga id from a command options that is either an id or URL.
-fn manga_id_from_option(url_or_id: &str) -> Option<Uuid> {
-    if let Ok(id) = Uuid::parse_str(url_or_id) {
-        Some(id)
-    } else if let Ok(url) = Url::parse(url_or_id) {
-        manga_id_from_url(url)
-    } else {
-        None
-    }
-}
-
-/// Parses a Mangadex URL to a specific manga extracting the manga id.
-fn manga_id_from_url(url: Url) -> Option<Uuid> {
-    if Some(Host::Domain("mangadex.org")) != url.host() {
-        return None;
-    }
-
-    let mut path_segments = url.path_segments()?;
-    if "title" != path_segments.next()? {
-        return None;
-    }
-
-    let id_str = path_segments.next()?;
-    Uuid::parse_str(id_str).ok()
-}
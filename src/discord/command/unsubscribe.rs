@@ -0,0 +1,99 @@
+//! The `unsubscribe` command tells the application to stop sending updates for a specific
+//! manga to the user that invoked the command.
+
+use std::sync::Arc;
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::{
+        command::CommandOptionType,
+        interaction::{
+            application_command::ApplicationCommandInteraction, InteractionResponseType,
+        },
+    },
+    prelude::Context,
+};
+
+use crate::db::{Store, Subscriber};
+
+use super::{manga_id_from_option, url_or_id, CommandError, SlashCommand};
+
+pub(super) struct Unsubscribe<S> {
+    pub(super) db_client: Arc<S>,
+}
+
+#[async_trait]
+impl<S: Store> SlashCommand for Unsubscribe<S> {
+    fn build<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("unsubscribe")
+            .description("Stop receiving updates for a given manga as direct messages.")
+            .create_option(|option| {
+                option
+                    .name("url")
+                    .description("Manga URL or Id.")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+            })
+    }
+
+    async fn run(
+        &self,
+        ctx: Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let say = |msg: String| {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| message.content(msg).ephemeral(true))
+            })
+        };
+
+        let options = command.data.options.as_slice();
+        tracing::info!(
+            command = command.data.name,
+            ?options,
+            "handling interaction"
+        );
+
+        // Extract the manga id from the command arguments.
+        let manga_id = url_or_id(options)
+            .and_then(manga_id_from_option)
+            .ok_or_else(|| {
+                tracing::error!(
+                    command = command.data.name,
+                    ?options,
+                    "url or id option missing or invalid"
+                );
+                CommandError::InvalidManga
+            })?
+            .to_string();
+
+        let subscriber = Subscriber::User(command.user.id);
+        let manga = self
+            .db_client
+            .read_manga(&manga_id)
+            .await?
+            .filter(|manga| manga.subscription(subscriber).is_some());
+
+        let Some(manga) = manga else {
+            tracing::info!(?subscriber, %manga_id, "user is not subscribed to this manga");
+            say(String::from("You are not subscribed to this manga.")).await?;
+            return Ok(());
+        };
+
+        self.db_client
+            .remove_subscribers(&manga, &[subscriber])
+            .await?;
+
+        // And send a response back to the user.
+        say(format!("Unsubscribed from {}.", manga.title)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! The `untrack` command tells the application to stop tracking a specific manga in the
+//! channel that the command was invoked in.
+
+use std::sync::Arc;
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::{
+        command::CommandOptionType,
+        interaction::{
+            application_command::ApplicationCommandInteraction, InteractionResponseType,
+        },
+    },
+    prelude::Context,
+};
+
+use crate::db::{Store, Subscriber};
+
+use super::{manga_id_from_option, url_or_id, CommandError, SlashCommand};
+
+pub(super) struct Untrack<S> {
+    pub(super) db_client: Arc<S>,
+}
+
+#[async_trait]
+impl<S: Store> SlashCommand for Untrack<S> {
+    fn build<'a>(
+        &self,
+        command: &'a mut CreateApplicationCommand,
+    ) -> &'a mut CreateApplicationCommand {
+        command
+            .name("untrack")
+            .description("Stop tracking updates for a given manga.")
+            .create_option(|option| {
+                option
+                    .name("url")
+                    .description("Manga URL or Id.")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+            })
+    }
+
+    async fn run(
+        &self,
+        ctx: Context,
+        command: &ApplicationCommandInteraction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let say = |msg: String| {
+            command.create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| message.content(msg))
+            })
+        };
+
+        let options = command.data.options.as_slice();
+        tracing::info!(
+            command = command.data.name,
+            ?options,
+            "handling interaction"
+        );
+
+        // Extract the manga id from the command arguments.
+        let manga_id = url_or_id(options)
+            .and_then(manga_id_from_option)
+            .ok_or_else(|| {
+                tracing::error!(
+                    command = command.data.name,
+                    ?options,
+                    "url or id option missing or invalid"
+                );
+                CommandError::InvalidManga
+            })?
+            .to_string();
+
+        // Only manga which are already tracked by this channel can be untracked.
+        let subscriber = Subscriber::Channel(command.channel_id);
+        let manga = self
+            .db_client
+            .read_manga(&manga_id)
+            .await?
+            .filter(|manga| manga.subscription(subscriber).is_some());
+
+        let Some(manga) = manga else {
+            tracing::info!(?subscriber, %manga_id, "channel does not track this manga");
+            say(String::from("This manga is not tracked by this channel.")).await?;
+            return Ok(());
+        };
+
+        self.db_client
+            .remove_subscribers(&manga, &[subscriber])
+            .await?;
+
+        // And send a response back to the user.
+        say(format!("No longer tracking {}.", manga.title)).await?;
+
+        Ok(())
+    }
+}
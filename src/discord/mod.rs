@@ -1,110 +1,408 @@
-use std::{sync::Arc, time::Duration};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 use serenity::{
     async_trait,
-    http::Http,
+    builder::CreateApplicationCommands,
+    client::bridge::gateway::event::ShardStageUpdateEvent,
+    gateway::ConnectionStage,
+    http::{Http, HttpError},
     model::{
-        application::interaction::Interaction,
-        prelude::{GuildId, Ready},
+        application::{
+            command::Command,
+            interaction::{Interaction, InteractionResponseType},
+        },
+        prelude::{Guild, GuildChannel, GuildId, PartialGuildChannel, Ready, UnavailableGuild},
     },
     prelude::*,
     Client,
 };
 
-use crate::db::MongoClient;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+use crate::{
+    db::{Store, Subscriber},
+    health::Health,
+    mangadex::MangaDexClient,
+    scan,
+};
 
 use self::command::SlashCommandMap;
 
 pub mod command;
 
+/// The JSON error code returned by discord when a message can't be sent to a user because
+/// they don't allow direct messages from the bot.
+pub const CANNOT_MESSAGE_USER: isize = 50007;
+
+/// The JSON error code returned by discord when a channel doesn't exist.
+pub const UNKNOWN_CHANNEL: isize = 10003;
+
+/// The JSON error code returned by discord when the bot isn't allowed to see a channel (e.g.,
+/// because it was removed from the guild or the channel's permissions changed).
+pub const MISSING_ACCESS: isize = 50001;
+
+/// Gets the JSON error code returned by discord for a failed request, if any.
+pub fn error_code(err: &serenity::Error) -> Option<isize> {
+    match err {
+        serenity::Error::Http(err) => match err.as_ref() {
+            HttpError::UnsuccessfulRequest(response) => Some(response.error.code),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Where the bot's application commands are registered.
+#[derive(Debug, Clone, Copy)]
+pub enum CommandScope {
+    /// Registers commands globally for all guilds.
+    ///
+    /// It may take upwards of an hour for discord to recognize changes to these commands.
+    Global,
+    /// Registers commands in a single guild, where changes take effect immediately.
+    Guild(GuildId),
+    /// Registers commands in every guild that the bot is in, including those it joins later.
+    EveryGuild,
+}
+
 /// Implementation of [EventHandler] for handling discord events.
-struct Handler {
-    guild_id: Option<u64>,
-    scan_period: u64,
-    db_client: Arc<MongoClient>,
+struct Handler<S> {
+    command_scope: CommandScope,
+    scan_config: scan::Config,
+    db_client: Arc<S>,
+    mangadex: Arc<MangaDexClient>,
+    health: Arc<Health>,
     commands: SlashCommandMap,
+    /// Cancelled when the bot is shutting down.
+    shutdown: CancellationToken,
+    /// Tracks the scan and any in-flight interactions so shutdown can wait for them.
+    tasks: TaskTracker,
+    /// Whether the scan has been started. `ready` is called again whenever the bot reconnects
+    /// but there must only ever be one scan.
+    scan_started: AtomicBool,
+    /// Whether application commands have been registered, so they aren't registered again
+    /// each time the bot reconnects.
+    commands_registered: AtomicBool,
 }
 
 #[async_trait]
-impl EventHandler for Handler {
+impl<S: Store> EventHandler for Handler<S> {
     async fn ready(&self, ctx: Context, ready: Ready) {
         tracing::info!(
             name = ready.user.name,
             "MangaDex discord bot is now connected!"
         );
+        self.health.set_connected(true);
 
-        // Setup application commands for this bot.
-        init_application_commands(&ctx.http, self.guild_id, &self.commands)
-            .await
-            .expect("failed to initialize application commands");
+        // Setup application commands for this bot once. Failing to do so shouldn't stop
+        // chapters from being announced, and registering them is tried again the next time the
+        // bot reconnects.
+        if !self.commands_registered.load(Ordering::SeqCst) {
+            match init_application_commands(&ctx.http, self.command_scope, &self.commands).await {
+                Ok(()) => self.commands_registered.store(true, Ordering::SeqCst),
+                Err(err) => tracing::error!(%err, "failed to initialize application commands"),
+            }
+        }
+
+        if self.scan_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
 
         // Spawn background tasks to scan for updates from MangaDex.
         let http = ctx.http.clone();
         let db_client = self.db_client.clone();
-        let period = Duration::from_secs(self.scan_period);
-        tokio::spawn(async move {
-            crate::scan::scan(http, db_client, period).await;
+        let mangadex = self.mangadex.clone();
+        let health = self.health.clone();
+        let config = self.scan_config.clone();
+        let shutdown = self.shutdown.clone();
+        self.tasks.spawn(async move {
+            scan::scan(http, db_client, mangadex, health, config, shutdown).await;
         });
     }
 
+    async fn shard_stage_update(&self, _ctx: Context, event: ShardStageUpdateEvent) {
+        tracing::info!(old = ?event.old, new = ?event.new, "gateway connection stage changed");
+        self.health
+            .set_connected(event.new == ConnectionStage::Connected);
+    }
+
+    async fn guild_create(&self, ctx: Context, guild: Guild) {
+        if let CommandScope::EveryGuild = self.command_scope {
+            // Failing to register commands in one guild shouldn't affect any of the others.
+            if let Err(err) = sync_guild_commands(&ctx.http, guild.id, &self.commands).await {
+                tracing::error!(%err, guild_id = %guild.id, "failed to sync application commands");
+            }
+        }
+    }
+
+    async fn channel_delete(&self, _ctx: Context, channel: &GuildChannel) {
+        tracing::info!(channel_id = %channel.id, "channel deleted");
+        let _ = self
+            .db_client
+            .remove_subscriber(Subscriber::Channel(channel.id))
+            .await;
+    }
+
+    async fn thread_delete(&self, _ctx: Context, thread: PartialGuildChannel) {
+        tracing::info!(channel_id = %thread.id, "thread deleted");
+        let _ = self
+            .db_client
+            .remove_subscriber(Subscriber::Channel(thread.id))
+            .await;
+    }
+
+    async fn guild_delete(&self, _ctx: Context, incomplete: UnavailableGuild) {
+        // Guilds also become unavailable during outages, in which case their channels will be
+        // back soon.
+        if incomplete.unavailable {
+            return;
+        }
+
+        tracing::info!(guild_id = %incomplete.id, "removed from guild");
+        let _ = self.db_client.remove_guild(incomplete.id).await;
+    }
+
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::ApplicationCommand(command) = interaction {
-            // Find the command handler from the list of registered commands.
-            if let Some(handler) = self.commands.get(&command.data.name) {
-                // Invoke the handler. Logging any error that occurs.
-                //
-                // It's also possible to response to the command with an error message here,
-                // however for now we'll just let the command timeout and discord will present an
-                // error message for us.
-                if let Err(err) = handler.run(ctx, &command).await {
-                    tracing::error!(%err, ?command, name = command.data.name, "error handling application command");
+        // Keep shutdown waiting until the interaction has been handled.
+        let _task = self.tasks.token();
+
+        match interaction {
+            Interaction::ApplicationCommand(command) => {
+                // Find the command handler from the list of registered commands.
+                if let Some(handler) = self.commands.get(&command.data.name) {
+                    // Acknowledge slow commands up front so that discord doesn't give up on
+                    // them while they're running.
+                    if handler.deferred() {
+                        let deferred = command
+                            .create_interaction_response(&ctx.http, |response| {
+                                response
+                                    .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                                    .interaction_response_data(|message| {
+                                        message.ephemeral(handler.ephemeral())
+                                    })
+                            })
+                            .await;
+                        if let Err(err) = deferred {
+                            tracing::error!(%err, name = command.data.name, "failed to defer application command");
+                            return;
+                        }
+                    }
+
+                    // Invoke the handler, logging any error that occurs and letting the user
+                    // know that something went wrong.
+                    let result = handler.run(ctx.clone(), &command).await;
+                    metrics::increment_counter!(
+                        "mangadex_bot_commands_total",
+                        "name" => command.data.name.clone(),
+                        "outcome" => if result.is_ok() { "success" } else { "error" },
+                    );
+                    if let Err(err) = result {
+                        tracing::error!(%err, ?command, name = command.data.name, "error handling application command");
+
+                        let reply = command::error_reply(err.as_ref());
+                        let result = if handler.deferred() {
+                            // The first followup to a public deferral replaces the "thinking"
+                            // message and stays public, so remove that message first.
+                            if !handler.ephemeral() {
+                                if let Err(err) = command
+                                    .delete_original_interaction_response(&ctx.http)
+                                    .await
+                                {
+                                    tracing::warn!(%err, name = command.data.name, "failed to delete deferred response");
+                                }
+                            }
+
+                            command
+                                .create_followup_message(&ctx.http, |message| {
+                                    message.content(reply).ephemeral(true)
+                                })
+                                .await
+                                .map(|_| ())
+                        } else {
+                            command
+                                .create_interaction_response(&ctx.http, |response| {
+                                    response
+                                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                                        .interaction_response_data(|message| {
+                                            message.content(reply).ephemeral(true)
+                                        })
+                                })
+                                .await
+                        };
+                        if let Err(err) = result {
+                            tracing::error!(%err, name = command.data.name, "failed to report error to user");
+                        }
+                    }
+                } else {
+                    tracing::warn!(?command, "unknown command");
                 }
-            } else {
-                tracing::warn!(?command, "unknown command");
             }
+            Interaction::Autocomplete(autocomplete) => {
+                if let Some(handler) = self.commands.get(&autocomplete.data.name) {
+                    if let Err(err) = handler.autocomplete(ctx, &autocomplete).await {
+                        tracing::error!(%err, ?autocomplete, name = autocomplete.data.name, "error handling autocomplete");
+                    }
+                } else {
+                    tracing::warn!(?autocomplete, "unknown command");
+                }
+            }
+            Interaction::MessageComponent(component) => {
+                // Components are owned by the command that created them.
+                let name = command::component_command_name(&component.data.custom_id);
+                if let Some(handler) = self.commands.get(name) {
+                    if let Err(err) = handler.run_component(ctx.clone(), &component).await {
+                        tracing::error!(%err, ?component, name, "error handling message component");
+
+                        let reply = command::error_reply(err.as_ref());
+                        let result = component
+                            .create_interaction_response(&ctx.http, |response| {
+                                response
+                                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                                    .interaction_response_data(|message| {
+                                        message.content(reply).ephemeral(true)
+                                    })
+                            })
+                            .await;
+                        if let Err(err) = result {
+                            tracing::error!(%err, name, "failed to report error to user");
+                        }
+                    }
+                } else {
+                    tracing::warn!(?component, "unknown message component");
+                }
+            }
+            _ => {}
         }
     }
 }
 
 /// Initializes and returns the discord client.
-pub async fn init(
+pub async fn init<S: Store>(
     token: &str,
-    guild_id: Option<u64>,
-    scan_period: u64,
-    db_client: Arc<MongoClient>,
+    command_scope: CommandScope,
+    scan_config: scan::Config,
+    db_client: Arc<S>,
+    mangadex: Arc<MangaDexClient>,
+    health: Arc<Health>,
     commands: SlashCommandMap,
+    shutdown: CancellationToken,
+    tasks: TaskTracker,
 ) -> serenity::Result<Client> {
     let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
 
     let handler = Handler {
-        guild_id,
-        scan_period,
+        command_scope,
+        scan_config,
         db_client,
+        mangadex,
+        health,
         commands,
+        shutdown,
+        tasks,
+        scan_started: AtomicBool::new(false),
+        commands_registered: AtomicBool::new(false),
     };
     Client::builder(token, intents).event_handler(handler).await
 }
 
+/// Registers the application commands for the scopes that don't depend on which guilds the
+/// bot is in.
 async fn init_application_commands(
     http: &Http,
-    guild_id: Option<u64>,
+    command_scope: CommandScope,
     commands: &SlashCommandMap,
 ) -> serenity::Result<()> {
-    if let Some(guild_id) = guild_id {
-        GuildId(guild_id)
-            .set_application_commands(http, |mut builder| {
-                for command in commands.values() {
-                    builder = builder.create_application_command(|builder| command.build(builder))
+    match command_scope {
+        CommandScope::Guild(guild_id) => sync_guild_commands(http, guild_id, commands)
+            .await
+            .map_err(|err| {
+                tracing::error!(%err, %guild_id, "failed to initialize guild specific application commands");
+                err
+            }),
+        CommandScope::Global => {
+            // The registered commands are only read to log those being removed, so failing
+            // to read them shouldn't stop the new ones from being registered.
+            match Command::get_global_application_commands(http).await {
+                Ok(registered) => log_stale_commands(&registered, commands, None),
+                Err(err) => {
+                    tracing::warn!(%err, "failed to read registered global application commands")
                 }
+            }
 
-                builder
+            Command::set_global_application_commands(http, |builder| {
+                create_commands(builder, commands)
             })
             .await
             .map_err(|err| {
-                tracing::error!(%err, %guild_id, "failed to initialize guild specific application commands");
+                tracing::error!(%err, "failed to initialize global application commands");
                 err
             })?;
+
+            Ok(())
+        }
+        CommandScope::EveryGuild => {
+            // Commands are registered as guilds become available. Any global commands left
+            // over from running in the global scope would show up twice so remove them.
+            let registered = Command::get_global_application_commands(http).await?;
+            if !registered.is_empty() {
+                for command in registered {
+                    tracing::info!(name = command.name, "removing global application command");
+                }
+
+                Command::set_global_application_commands(http, |builder| builder).await?;
+            }
+
+            Ok(())
+        }
     }
+}
+
+/// Replaces the application commands registered in a guild with a given set of commands,
+/// removing any which are no longer implemented.
+async fn sync_guild_commands(
+    http: &Http,
+    guild_id: GuildId,
+    commands: &SlashCommandMap,
+) -> serenity::Result<()> {
+    let registered = guild_id.get_application_commands(http).await?;
+    log_stale_commands(&registered, commands, Some(guild_id));
+
+    guild_id
+        .set_application_commands(http, |builder| create_commands(builder, commands))
+        .await?;
 
     Ok(())
 }
+
+/// Adds every command in a given set to a builder.
+fn create_commands<'a>(
+    mut builder: &'a mut CreateApplicationCommands,
+    commands: &SlashCommandMap,
+) -> &'a mut CreateApplicationCommands {
+    for command in commands.values() {
+        builder = builder.create_application_command(|builder| command.build(builder))
+    }
+
+    builder
+}
+
+/// Logs the registered commands which aren't part of a given set, and will therefore be
+/// removed when the set is registered.
+fn log_stale_commands(
+    registered: &[Command],
+    commands: &SlashCommandMap,
+    guild_id: Option<GuildId>,
+) {
+    for command in registered {
+        if !commands.contains_key(&command.name) {
+            tracing::info!(
+                name = command.name,
+                ?guild_id,
+                "removing stale application command"
+            );
+        }
+    }
+}
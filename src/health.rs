@@ -0,0 +1,88 @@
+//! The `health` module tracks the state that the health and readiness of the bot are judged
+//! by.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// The state of the bot's connection to discord and of the scan for new chapters.
+#[derive(Debug)]
+pub struct Health {
+    started: Instant,
+    connected: AtomicBool,
+    last_scan: Mutex<Option<Instant>>,
+}
+
+impl Health {
+    /// Creates the state for a bot that has just started.
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            connected: AtomicBool::new(false),
+            last_scan: Mutex::new(None),
+        }
+    }
+
+    /// Records whether the bot is currently connected to the discord gateway.
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    /// Whether the bot is currently connected to the discord gateway.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Records that a scan for new chapters just completed successfully.
+    pub fn scan_succeeded(&self) {
+        *self.last_scan.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// How long ago the last successful scan completed, if any has.
+    pub fn last_scan_age(&self) -> Option<Duration> {
+        self.last_scan.lock().unwrap().map(|at| at.elapsed())
+    }
+
+    /// Whether scanning has stalled, having not succeeded within a given duration.
+    ///
+    /// The first scan is given the same duration from when the bot started to complete.
+    pub fn is_scan_stalled(&self, threshold: Duration) -> bool {
+        let age = self
+            .last_scan_age()
+            .unwrap_or_else(|| self.started.elapsed());
+        age > threshold
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_scan_is_given_time_to_complete() {
+        let health = Health::new();
+        assert!(!health.is_scan_stalled(Duration::from_secs(60)));
+        assert!(health.is_scan_stalled(Duration::ZERO));
+    }
+
+    #[test]
+    fn successful_scan_resets_stall() {
+        let health = Health::new();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(health.is_scan_stalled(Duration::from_millis(10)));
+
+        health.scan_succeeded();
+        assert!(!health.is_scan_stalled(Duration::from_millis(10)));
+        assert!(health.last_scan_age().is_some());
+    }
+}
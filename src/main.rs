@@ -1,10 +1,28 @@
-use clap::{command, Parser};
-use db::MongoClient;
+use std::{
+    net::SocketAddr,
+    num::NonZeroUsize,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use clap::Parser;
+use db::{MemoryStore, MongoClient, SqliteStore, Store};
+use discord::CommandScope;
+use health::Health;
+use mangadex::{MangaDexClient, RetryPolicy};
+use reqwest::Url;
+use serenity::model::prelude::GuildId;
+use tokio::time::Instant;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 
 mod db;
 mod discord;
+mod health;
 mod mangadex;
+#[cfg(test)]
+mod mock;
 mod scan;
+mod server;
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -21,21 +39,126 @@ struct Args {
     #[arg(long, env = "MANGADEX_BOT_GUILD_ID")]
     guild_id: Option<u64>,
 
+    /// Register application commands in every guild the bot is in instead of
+    /// globally.
+    ///
+    /// Changes to guild commands take effect immediately. Commands which are
+    /// no longer implemented are removed from each guild.
+    #[arg(
+        long,
+        env = "MANGADEX_BOT_SYNC_GUILD_COMMANDS",
+        conflicts_with = "guild_id"
+    )]
+    sync_guild_commands: bool,
+
+    /// The URL of a SQLite database to store everything in (e.g.,
+    /// `sqlite://mangadex-bot.db`).
+    ///
+    /// The database is created if it doesn't exist.
+    #[arg(
+        long,
+        env = "MANGADEX_BOT_DATABASE_URL",
+        conflicts_with = "connection_string"
+    )]
+    database_url: Option<String>,
+
     /// The connection string of the Azure Cosmos DB for MongoDB account.
-    #[arg(long, env = "MANGADEX_BOT_CONNECTION_STRING")]
-    connection_string: String,
+    #[arg(
+        long,
+        env = "MANGADEX_BOT_CONNECTION_STRING",
+        requires_all = ["database", "collection"]
+    )]
+    connection_string: Option<String>,
 
     /// The name of the database within the account.
     #[arg(long, env = "MANGADEX_BOT_DATABASE")]
-    database: String,
+    database: Option<String>,
 
     /// The name of the collection within the database.
     #[arg(long, env = "MANGADEX_BOT_COLLECTION")]
-    collection: String,
+    collection: Option<String>,
+
+    /// The name of the collection within the database that guild settings are stored in.
+    #[arg(
+        long,
+        env = "MANGADEX_BOT_SETTINGS_COLLECTION",
+        default_value = "settings"
+    )]
+    settings_collection: String,
+
+    /// The name of the collection within the database that announcements
+    /// waiting to be delivered are stored in.
+    #[arg(long, env = "MANGADEX_BOT_OUTBOX_COLLECTION", default_value = "outbox")]
+    outbox_collection: String,
+
+    /// Store everything in memory, where it's lost when the application
+    /// exits, instead of in a database.
+    ///
+    /// One of this, a database URL or a connection string must be given.
+    #[arg(
+        long,
+        env = "MANGADEX_BOT_IN_MEMORY",
+        conflicts_with_all = ["database_url", "connection_string"]
+    )]
+    in_memory: bool,
 
     /// The period between scans in seconds (default 6 hours).
     #[arg(long, env = "MANGADEX_BOT_SCAN_PERIOD", default_value = "21600")]
     scan_period: u64,
+
+    /// The number of new chapters for a single manga above which they are announced with a
+    /// single summary message instead of one message per chapter.
+    #[arg(long, env = "MANGADEX_BOT_BULK_THRESHOLD", default_value = "5")]
+    bulk_threshold: usize,
+
+    /// The maximum number of manga checked for new chapters, and of channels
+    /// and users sent announcements, at once.
+    ///
+    /// Requests to MangaDex are still subject to its global rate limit.
+    #[arg(long, env = "MANGADEX_BOT_SCAN_CONCURRENCY", default_value = "8")]
+    scan_concurrency: NonZeroUsize,
+
+    /// The base URL of the MangaDex API.
+    ///
+    /// May point at a mock server or caching proxy instead of MangaDex itself.
+    #[arg(
+        long,
+        env = "MANGADEX_BOT_MANGADEX_API_URL",
+        default_value = mangadex::DEFAULT_API_URL
+    )]
+    mangadex_api_url: Url,
+
+    /// The number of seconds after which requests to the MangaDex API time out.
+    #[arg(long, env = "MANGADEX_BOT_MANGADEX_TIMEOUT", default_value = "30")]
+    mangadex_timeout: u64,
+
+    /// The number of seconds for which requests to the MangaDex API that fail for transient
+    /// reasons are retried before giving up.
+    #[arg(
+        long,
+        env = "MANGADEX_BOT_MANGADEX_RETRY_DEADLINE",
+        default_value = "60"
+    )]
+    mangadex_retry_deadline: u64,
+
+    /// The address to serve Prometheus metrics and health reports on (e.g.,
+    /// `0.0.0.0:9090`).
+    ///
+    /// Metrics are served from `/metrics` while health and readiness are
+    /// reported by `/healthz` and `/readyz`. If not specified, no metrics are
+    /// collected.
+    #[arg(long, env = "MANGADEX_BOT_HTTP_ADDRESS")]
+    http_address: Option<SocketAddr>,
+
+    /// The number of scan periods without a successful scan after which the
+    /// bot is no longer reported as ready.
+    #[arg(long, env = "MANGADEX_BOT_SCAN_STALL_PERIODS", default_value = "3")]
+    scan_stall_periods: u32,
+
+    /// The number of seconds to wait, after being asked to stop, for the
+    /// current scan and any in-flight commands to finish before exiting.
+    #[arg(long, env = "MANGADEX_BOT_SHUTDOWN_DEADLINE", default_value = "30")]
+    shutdown_deadline: u64,
 }
 
 #[tokio::main]
@@ -45,18 +168,145 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let subscriber = tracing_subscriber::FmtSubscriber::new();
     tracing::subscriber::set_global_default(subscriber)?;
 
-    let db_client =
-        MongoClient::connect(&args.connection_string, &args.database, &args.collection).await?;
-    let commands = discord::command::init(&args, db_client.clone());
+    if let Some(database_url) = args.database_url.as_deref() {
+        let db_client = SqliteStore::connect(database_url).await?;
+        return run(&args, db_client).await;
+    }
+
+    match args.connection_string.as_deref() {
+        Some(connection_string) => {
+            let db_client = MongoClient::connect(
+                connection_string,
+                args.database.as_deref().unwrap_or_default(),
+                args.collection.as_deref().unwrap_or_default(),
+                &args.settings_collection,
+                &args.outbox_collection,
+            )
+            .await?;
+            run(&args, db_client).await
+        }
+        None if args.in_memory => {
+            tracing::warn!("storing everything in memory");
+            run(&args, Arc::new(MemoryStore::new())).await
+        }
+        // Falling back to memory would quietly lose everything when the bot restarts if the
+        // database was left out by mistake.
+        None => {
+            Err("no storage given: use --database-url, --connection-string or --in-memory".into())
+        }
+    }
+}
+
+/// Runs the discord bot using a given storage backend.
+async fn run<S: Store>(
+    args: &Args,
+    db_client: Arc<S>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mangadex = Arc::new(
+        MangaDexClient::new(
+            args.mangadex_api_url.clone(),
+            Duration::from_secs(args.mangadex_timeout),
+        )?
+        .with_retry_policy(RetryPolicy::new(Duration::from_secs(
+            args.mangadex_retry_deadline,
+        ))),
+    );
+
+    let health = Arc::new(Health::new());
+    if let Some(address) = args.http_address {
+        let metrics = server::install_recorder()?;
+        let stall_threshold = Duration::from_secs(args.scan_period) * args.scan_stall_periods;
+        server::spawn(
+            address,
+            metrics,
+            health.clone(),
+            db_client.clone(),
+            stall_threshold,
+        )?;
+    }
+
+    let shutdown = CancellationToken::new();
+    let tasks = TaskTracker::new();
+
+    let commands = discord::command::init(args, db_client.clone(), mangadex.clone());
+    let command_scope = match args.guild_id {
+        Some(guild_id) => CommandScope::Guild(GuildId(guild_id)),
+        None if args.sync_guild_commands => CommandScope::EveryGuild,
+        None => CommandScope::Global,
+    };
     let mut client = discord::init(
         &args.discord_token,
-        args.guild_id,
-        args.scan_period,
+        command_scope,
+        scan::Config {
+            period: Duration::from_secs(args.scan_period),
+            bulk_threshold: args.bulk_threshold,
+            concurrency: args.scan_concurrency.get(),
+        },
         db_client,
+        mangadex,
+        health,
         commands,
+        shutdown.clone(),
+        tasks.clone(),
     )
     .await?;
-    client.start().await?;
 
-    Ok(())
+    // Stop scanning and disconnect from discord once asked to stop. The client stops running
+    // once all of its shards have been shut down. The shutdown deadline runs from when we are
+    // asked to stop, so time spent disconnecting counts towards it.
+    let shutdown_deadline = Duration::from_secs(args.shutdown_deadline);
+    let stop_by = Arc::new(OnceLock::new());
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn({
+        let stop_by = Arc::clone(&stop_by);
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown_signal().await;
+            stop_by.get_or_init(|| Instant::now() + shutdown_deadline);
+            tracing::info!("shutting down");
+            shutdown.cancel();
+            shard_manager.lock().await.shutdown_all().await;
+        }
+    });
+
+    // The client also stops if it fails, e.g. because the token is invalid, so make sure the
+    // scan stops too before returning any error.
+    let result = client.start().await;
+    shutdown.cancel();
+
+    // Give the scan and any in-flight commands until the deadline to finish.
+    tasks.close();
+    let stop_by = *stop_by.get_or_init(|| Instant::now() + shutdown_deadline);
+    if tokio::time::timeout_at(stop_by, tasks.wait())
+        .await
+        .is_err()
+    {
+        tracing::warn!(deadline = ?shutdown_deadline, "exiting before all tasks finished");
+    }
+
+    result.map_err(Into::into)
+}
+
+/// Waits until the process is asked to stop by either SIGINT or, on unix, SIGTERM.
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
 }
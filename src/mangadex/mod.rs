@@ -0,0 +1,1094 @@
+//! The `mangadex` module contains types and functions for interacting with the
+//! [MangaDex API](https://api.mangadex.org/docs/).
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime, UtcOffset,
+};
+
+use tokio::time::Instant;
+
+use self::rate_limit::RateLimiter;
+pub use self::retry::RetryPolicy;
+
+mod rate_limit;
+mod retry;
+
+/// The base URL of the MangaDex API unless configured otherwise.
+pub const DEFAULT_API_URL: &str = "https://api.mangadex.org";
+
+const WEB_SITE: &str = "https://mangadex.org";
+const UPLOADS_SITE: &str = "https://uploads.mangadex.org";
+
+/// The translated language that chapters are announced in unless configured otherwise.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// The maximum number of manga ids to include in a single request.
+const MANGA_PER_REQUEST: usize = 100;
+
+/// The maximum number of entities the API will return in a single page.
+const PAGE_LIMIT: u32 = 100;
+
+/// The API refuses to return pages past this offset.
+const MAX_OFFSET: u32 = 10_000;
+
+/// The User-Agent sent with every request, as MangaDex asks that clients identify themselves.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The maximum number of characters of a response body to keep when it can't be decoded.
+const BODY_SNIPPET_LENGTH: usize = 256;
+
+/// An error returned by the MangaDex API.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiError {
+    pub id: String,
+    pub status: i32,
+    pub title: String,
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.detail.as_deref() {
+            Some(detail) => f.write_str(detail),
+            None => f.write_str(&self.title),
+        }
+    }
+}
+
+/// Errors returned by Mangadex operations.
+#[derive(Debug)]
+pub enum Error {
+    /// The request timed out.
+    Timeout(reqwest::Error),
+    /// A connection to the API could not be established.
+    Connection(reqwest::Error),
+    /// Some other error occurred while sending the request or receiving the response.
+    Network(reqwest::Error),
+    /// The requested entity does not exist.
+    NotFound(Vec<ApiError>),
+    /// Too many requests have been made to the API.
+    RateLimited(Vec<ApiError>),
+    /// The API failed to handle the request.
+    Server(StatusCode, Vec<ApiError>),
+    /// The API rejected the request with some other status code.
+    Status(StatusCode, Vec<ApiError>),
+    /// The response could not be decoded, along with the start of the response body.
+    Decode {
+        source: serde_json::Error,
+        body: String,
+    },
+    /// The API returned errors in an otherwise successful response.
+    Api(Vec<ApiError>),
+    /// There were more results, the given total, than the API allows paging through.
+    TooManyResults(u32),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Error::*;
+
+        match &self {
+            Timeout(_) => f.write_str("The request to MangaDex timed out."),
+            Connection(_) => f.write_str("Could not connect to MangaDex."),
+            Network(_) => f.write_str("An error occurred while communicating with the MangaDex."),
+            NotFound(errors) => {
+                f.write_str("The requested entity does not exist on MangaDex.")?;
+                write_api_errors(f, errors)
+            }
+            RateLimited(errors) => {
+                f.write_str("Too many requests have been made to MangaDex.")?;
+                write_api_errors(f, errors)
+            }
+            Server(status, errors) => {
+                write!(f, "MangaDex is unavailable ({status}).")?;
+                write_api_errors(f, errors)
+            }
+            Status(status, errors) => {
+                write!(f, "MangaDex rejected the request ({status}).")?;
+                write_api_errors(f, errors)
+            }
+            Decode { source, body } => write!(
+                f,
+                "The response from MangaDex could not be decoded: {source}: {body}"
+            ),
+            Api(errors) => match errors.len() {
+                0 => f.write_str("An error was returned by the MangaDex API."),
+                1 => write!(
+                    f,
+                    "An error was returned by the MangaDex API: {}",
+                    errors[0]
+                ),
+                _ => f.write_str(
+                    "Many errors were returned by the MangaDex API, see logs for more information.",
+                ),
+            },
+            TooManyResults(total) => write!(
+                f,
+                "MangaDex has too many results ({total}) to page through."
+            ),
+        }
+    }
+}
+
+/// Appends the first of the errors returned by the API, if any, to an error message.
+fn write_api_errors(f: &mut std::fmt::Formatter<'_>, errors: &[ApiError]) -> std::fmt::Result {
+    match errors.first() {
+        Some(error) => write!(f, " {error}"),
+        None => Ok(()),
+    }
+}
+
+impl Error {
+    /// Whether this error is likely to go away if the request is retried.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Error::Timeout(_) | Error::Connection(_) | Error::RateLimited(_) | Error::Server(..)
+        )
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use Error::*;
+
+        match self {
+            Timeout(err) | Connection(err) | Network(err) => Some(err),
+            Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Result type returned by Mangadex operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Models a response from the MangaDex API that contains a single entity.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "result")]
+#[serde(rename_all = "camelCase")]
+enum EntityResponse<T> {
+    Ok { data: T },
+    Error { errors: Vec<ApiError> },
+}
+
+impl<T> EntityResponse<T> {
+    /// Converts this response into a [Result].
+    fn into_result(self) -> Result<T> {
+        match self {
+            EntityResponse::Ok { data } => Ok(data),
+            EntityResponse::Error { errors } => Err(Error::Api(errors)),
+        }
+    }
+}
+
+/// Models a response from the MangaDex API that contains multiple entities.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "result")]
+#[serde(rename_all = "camelCase")]
+enum CollectionResponse<T> {
+    Ok {
+        data: Vec<T>,
+        #[serde(default)]
+        total: u32,
+    },
+    Error {
+        errors: Vec<ApiError>,
+    },
+}
+
+impl<T> CollectionResponse<T> {
+    /// Converts this response into a [Result].
+    fn into_result(self) -> Result<Vec<T>> {
+        self.into_page().map(|(data, _)| data)
+    }
+
+    /// Converts this response into a [Result] containing a single page of entities along with
+    /// the total number of entities across all pages.
+    fn into_page(self) -> Result<(Vec<T>, u32)> {
+        match self {
+            CollectionResponse::Ok { data, total } => Ok((data, total)),
+            CollectionResponse::Error { errors } => Err(Error::Api(errors)),
+        }
+    }
+}
+
+/// Models a response from the MangaDex API with an unsuccessful status code.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    errors: Vec<ApiError>,
+}
+
+/// Models a relationship between an entity and some other entity.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Relationship {
+    Manga {
+        id: String,
+        attributes: Option<MangaAttributes>,
+    },
+    CoverArt {
+        id: String,
+        attributes: Option<CoverArtAttributes>,
+    },
+    ScanlationGroup {
+        id: String,
+        attributes: Option<ScanlationGroupAttributes>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverArtAttributes {
+    pub file_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScanlationGroupAttributes {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manga {
+    pub id: String,
+    pub attributes: MangaAttributes,
+    #[serde(default)]
+    pub relationships: Vec<Relationship>,
+}
+
+impl Manga {
+    /// Gets the URL of a thumbnail of this manga's cover art.
+    ///
+    /// The cover art is only available if it was included when the manga was fetched.
+    pub fn cover_art_url(&self) -> Option<Url> {
+        self.relationships.iter().find_map(|r| match r {
+            Relationship::CoverArt {
+                attributes: Some(attributes),
+                ..
+            } => Url::parse(UPLOADS_SITE)
+                .unwrap()
+                .join(&format!(
+                    "/covers/{}/{}.256.jpg",
+                    self.id, attributes.file_name
+                ))
+                .ok(),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MangaAttributes {
+    pub title: HashMap<String, String>,
+    pub content_rating: Option<ContentRating>,
+}
+
+/// The content rating of a manga.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentRating {
+    Safe,
+    Suggestive,
+    Erotica,
+    Pornographic,
+}
+
+impl ContentRating {
+    /// All content ratings from least to most explicit.
+    pub const ALL: [ContentRating; 4] = [
+        ContentRating::Safe,
+        ContentRating::Suggestive,
+        ContentRating::Erotica,
+        ContentRating::Pornographic,
+    ];
+
+    /// The content ratings that are allowed unless configured otherwise.
+    pub const DEFAULT: [ContentRating; 2] = [ContentRating::Safe, ContentRating::Suggestive];
+
+    /// The value of this rating as used by the API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentRating::Safe => "safe",
+            ContentRating::Suggestive => "suggestive",
+            ContentRating::Erotica => "erotica",
+            ContentRating::Pornographic => "pornographic",
+        }
+    }
+
+    /// Whether manga with this rating may only be shown in channels marked as NSFW.
+    pub fn is_nsfw(&self) -> bool {
+        matches!(self, ContentRating::Erotica | ContentRating::Pornographic)
+    }
+}
+
+impl std::fmt::Display for ContentRating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl MangaAttributes {
+    /// Gets the english title for this manga if it exists.
+    ///
+    /// If the manga does not have an English title, the romanized Japanese or Chinese
+    /// title is returned instead.
+    pub fn english_title(&self) -> Option<&str> {
+        self.title
+            .get("en")
+            .or_else(|| self.title.get("ja-ro"))
+            .or_else(|| self.title.get("zh-ro"))
+            .map(|x| x.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Chapter {
+    pub id: String,
+    pub attributes: ChapterAttributes,
+    #[serde(default)]
+    pub relationships: Vec<Relationship>,
+}
+
+impl Chapter {
+    pub fn url(&self) -> Url {
+        chapter_url(&self.id)
+    }
+
+    /// Gets the id of the manga that this chapter belongs to.
+    pub fn manga_id(&self) -> Option<&str> {
+        self.relationships.iter().find_map(|r| match r {
+            Relationship::Manga { id, .. } => Some(id.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Gets the content rating of the manga that this chapter belongs to.
+    ///
+    /// The content rating is only available if the manga was included when the chapter was
+    /// fetched.
+    pub fn content_rating(&self) -> Option<ContentRating> {
+        self.relationships.iter().find_map(|r| match r {
+            Relationship::Manga {
+                attributes: Some(attributes),
+                ..
+            } => attributes.content_rating,
+            _ => None,
+        })
+    }
+
+    /// Gets when this chapter was published, if it has a valid publish time.
+    pub fn publish_at(&self) -> Option<OffsetDateTime> {
+        parse_timestamp(self.attributes.publish_at.as_deref()?)
+    }
+
+    /// Gets the name of the scanlation group that translated this chapter.
+    ///
+    /// The name is only available if the group was included when the chapter was fetched.
+    pub fn scanlation_group(&self) -> Option<&str> {
+        self.relationships.iter().find_map(|r| match r {
+            Relationship::ScanlationGroup {
+                attributes: Some(attributes),
+                ..
+            } => Some(attributes.name.as_str()),
+            _ => None,
+        })
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterAttributes {
+    pub title: Option<String>,
+    pub volume: Option<String>,
+    pub chapter: Option<String>,
+    pub pages: i32,
+    pub translated_language: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub publish_at: Option<String>,
+    pub readable_at: Option<String>,
+}
+
+/// Checks whether a string looks like a MangaDex language code (e.g., "en" or "pt-br").
+pub fn is_language_code(s: &str) -> bool {
+    let is_part = |p: &str| (2..=3).contains(&p.len()) && p.chars().all(|c| c.is_ascii_lowercase());
+    match s.split_once('-') {
+        Some((language, region)) => is_part(language) && is_part(region),
+        None => is_part(s),
+    }
+}
+
+/// Constructs the URL of the MangaDex web page for a manga with a given id.
+pub fn manga_url(manga_id: &str) -> Url {
+    Url::parse(WEB_SITE)
+        .unwrap()
+        .join("/title/")
+        .unwrap()
+        .join(manga_id)
+        .unwrap()
+}
+
+/// Constructs the URL of the MangaDex web page for a chapter with a given id.
+pub fn chapter_url(chapter_id: &str) -> Url {
+    Url::parse(WEB_SITE)
+        .unwrap()
+        .join("/chapter/")
+        .unwrap()
+        .join(chapter_id)
+        .unwrap()
+}
+
+/// Parses a timestamp as returned by the API (e.g., "2023-01-02T00:00:00+00:00").
+pub fn parse_timestamp(timestamp: &str) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(timestamp, &Rfc3339).ok()
+}
+
+/// Client for the MangaDex API.
+///
+/// All requests made through a client share a single HTTP connection pool and rate limiter.
+/// Requests that fail for transient reasons are retried according to the client's
+/// [RetryPolicy].
+#[derive(Debug)]
+pub struct MangaDexClient {
+    /// The base URL of the API, always ending with a `/`.
+    base_url: Url,
+    http: reqwest::Client,
+    rate_limiter: RateLimiter,
+    retry_policy: RetryPolicy,
+}
+
+impl MangaDexClient {
+    /// Creates a client for the API at a given base URL whose requests time out after a given
+    /// duration.
+    pub fn new(base_url: Url, timeout: Duration) -> reqwest::Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(timeout)
+            .timeout(timeout)
+            .build()?;
+
+        Ok(Self::with_http_client(base_url, http))
+    }
+
+    /// Creates a client for the API at a given base URL which sends requests using a given
+    /// HTTP client.
+    pub fn with_http_client(mut base_url: Url, http: reqwest::Client) -> Self {
+        // Endpoints are joined onto the base URL so make sure it is treated as a directory.
+        if !base_url.path().ends_with('/') {
+            let path = format!("{}/", base_url.path());
+            base_url.set_path(&path);
+        }
+
+        Self {
+            base_url,
+            http,
+            rate_limiter: RateLimiter::mangadex(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets how many requests may be made every `period`, instead of MangaDex's published
+    /// global limit.
+    #[cfg(test)]
+    pub fn with_rate_limit(mut self, requests: u32, period: Duration) -> Self {
+        self.rate_limiter = RateLimiter::new(requests, period);
+        self
+    }
+
+    /// Sets the policy used to retry requests that fail for transient reasons.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Constructs the URL of an API endpoint relative to the base URL.
+    fn endpoint(&self, path: &str) -> Url {
+        self.base_url.join(path).unwrap()
+    }
+
+    /// Gets the path of the endpoint that a URL points to with any ids replaced by `{id}`,
+    /// for labelling metrics.
+    fn endpoint_label(&self, url: &Url) -> String {
+        let path = url
+            .path()
+            .strip_prefix(self.base_url.path())
+            .unwrap_or(url.path());
+
+        path.split('/')
+            .map(|segment| {
+                let is_id = segment.len() == 36
+                    && segment.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+                if is_id {
+                    "{id}"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Retrieves the manga with a given id.
+    #[tracing::instrument(err, ret, skip(self))]
+    pub async fn manga(&self, manga_id: &str) -> Result<Manga> {
+        let url = self.endpoint(&format!("manga/{manga_id}"));
+
+        self.fetch_json::<EntityResponse<Manga>>(url)
+            .await?
+            .into_result()
+    }
+
+    /// Retrieves the english title for a manga with a given id.
+    #[tracing::instrument(err, ret, skip(self))]
+    pub async fn english_title(&self, manga_id: &str) -> Result<Option<String>> {
+        let manga = self.manga(manga_id).await?;
+
+        let title = manga.attributes.english_title().map(|s| s.to_owned());
+        Ok(title)
+    }
+
+    /// Retrieves the URL of the cover art thumbnail for a manga with a given id.
+    #[tracing::instrument(err, ret, skip(self))]
+    pub async fn cover_art_url(&self, manga_id: &str) -> Result<Option<Url>> {
+        let mut url = self.endpoint(&format!("manga/{manga_id}"));
+        url.query_pairs_mut().append_pair("includes[]", "cover_art");
+
+        let manga = self
+            .fetch_json::<EntityResponse<Manga>>(url)
+            .await?
+            .into_result()?;
+
+        Ok(manga.cover_art_url())
+    }
+
+    /// Searches for manga with one of the given content ratings whose title matches a given
+    /// query, returning at most `limit` results ordered by relevance.
+    #[tracing::instrument(err, ret, skip(self))]
+    pub async fn search_manga(
+        &self,
+        title: &str,
+        content_ratings: &[ContentRating],
+        limit: u32,
+    ) -> Result<Vec<Manga>> {
+        let mut url = self.endpoint("manga");
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("title", title)
+                .append_pair("limit", &limit.to_string())
+                .append_pair("order[relevance]", "desc");
+
+            for rating in content_ratings {
+                query.append_pair("contentRating[]", rating.as_str());
+            }
+        }
+
+        self.fetch_json::<CollectionResponse<Manga>>(url)
+            .await?
+            .into_result()
+    }
+
+    /// Fetches the latest chapter for a given manga in a given translated language, provided
+    /// the manga has one of the given content ratings.
+    #[tracing::instrument(err, ret, skip(self))]
+    pub async fn latest_chapter(
+        &self,
+        manga_id: &str,
+        language: &str,
+        content_ratings: &[ContentRating],
+    ) -> Result<Option<Chapter>> {
+        let url = self.latest_chapter_url(manga_id, language, content_ratings);
+
+        let mut chapter = self
+            .fetch_json::<CollectionResponse<Chapter>>(url)
+            .await?
+            .into_result()?;
+
+        Ok(chapter.pop())
+    }
+
+    /// Fetches all chapters, for any of the given manga and in any of the given translated
+    /// languages, that were published at or after a given time.
+    ///
+    /// Chapters are returned in the order they were published. Multiple requests are made if
+    /// there are too many manga to fit in a single request or too many chapters to fit in a
+    /// single page. The API won't page past [MAX_OFFSET], so once that is reached the
+    /// remaining chapters are fetched by starting again from when the last chapter seen was
+    /// published.
+    #[tracing::instrument(err, skip(self, manga_ids), fields(manga = manga_ids.len()))]
+    pub async fn chapters_published_since(
+        &self,
+        manga_ids: &[&str],
+        languages: &[&str],
+        since: OffsetDateTime,
+    ) -> Result<Vec<Chapter>> {
+        let mut chapters = Vec::new();
+        let mut seen = HashSet::new();
+        for chunk in manga_ids.chunks(MANGA_PER_REQUEST) {
+            let mut since = since;
+            let mut offset = 0;
+            let mut last_publish_at = None;
+            loop {
+                let url = self.chapters_published_since_url(chunk, languages, since, offset);
+                let (page, total) = self
+                    .fetch_json::<CollectionResponse<Chapter>>(url)
+                    .await?
+                    .into_page()?;
+
+                offset += page.len() as u32;
+                let done = page.is_empty() || offset >= total;
+                if let Some(publish_at) = page.last().and_then(|c| c.publish_at()) {
+                    last_publish_at = Some(publish_at);
+                }
+                // Chapters published at the start of a window have already been seen if the
+                // window was split.
+                chapters.extend(page.into_iter().filter(|c| seen.insert(c.id.clone())));
+
+                if done {
+                    break;
+                }
+
+                if offset + PAGE_LIMIT > MAX_OFFSET {
+                    match last_publish_at {
+                        Some(publish_at) if publish_at > since => {
+                            tracing::debug!(total, %publish_at, "too many chapters to page through, splitting window");
+                            since = publish_at;
+                            offset = 0;
+                        }
+                        _ => return Err(Error::TooManyResults(total)),
+                    }
+                }
+            }
+        }
+
+        // The API is asked to filter by manga, but filter again just in case so the caller
+        // never sees chapters for manga it didn't ask for.
+        chapters.retain(|c| matches!(c.manga_id(), Some(id) if manga_ids.contains(&id)));
+        chapters.sort_by(|a, b| a.attributes.publish_at.cmp(&b.attributes.publish_at));
+
+        Ok(chapters)
+    }
+
+    /// Constructs a URL that fetches a page of chapters published since a given time.
+    fn chapters_published_since_url(
+        &self,
+        manga_ids: &[&str],
+        languages: &[&str],
+        since: OffsetDateTime,
+        offset: u32,
+    ) -> Url {
+        let since = since
+            .to_offset(UtcOffset::UTC)
+            .format(format_description!(
+                "[year]-[month]-[day]T[hour]:[minute]:[second]"
+            ))
+            .unwrap();
+
+        let mut url = self.endpoint("chapter");
+        {
+            let mut query = url.query_pairs_mut();
+            for id in manga_ids {
+                query.append_pair("manga[]", id);
+            }
+
+            for language in languages {
+                query.append_pair("translatedLanguage[]", language);
+            }
+
+            // Content ratings are filtered per channel by the caller.
+            for rating in ContentRating::ALL {
+                query.append_pair("contentRating[]", rating.as_str());
+            }
+
+            query
+                .append_pair("publishAtSince", &since)
+                .append_pair("limit", &PAGE_LIMIT.to_string())
+                .append_pair("offset", &offset.to_string())
+                .append_pair("includeFutureUpdates", "0")
+                .append_pair("includes[]", "manga")
+                .append_pair("includes[]", "scanlation_group")
+                .append_pair("order[publishAt]", "asc");
+        }
+
+        url
+    }
+
+    /// Constructs a URL that fetches the latest chapter for a given manga.
+    fn latest_chapter_url(
+        &self,
+        manga_id: &str,
+        language: &str,
+        content_ratings: &[ContentRating],
+    ) -> Url {
+        let mut url = self.endpoint("chapter");
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("manga", manga_id)
+                .append_pair("limit", "1")
+                .append_pair("translatedLanguage[]", language)
+                .append_pair("order[chapter]", "desc");
+
+            for rating in content_ratings {
+                query.append_pair("contentRating[]", rating.as_str());
+            }
+        }
+
+        url
+    }
+
+    /// Sends an HTTP GET request to a given url decoding the response, if successful, from
+    /// JSON.
+    ///
+    /// The request is retried if it fails for a transient reason until the deadline of the
+    /// client's retry policy passes.
+    #[tracing::instrument(err, ret, skip(self))]
+    async fn fetch_json<T>(&self, url: Url) -> Result<T>
+    where
+        T: std::fmt::Debug,
+        T: serde::de::DeserializeOwned,
+    {
+        let deadline = Instant::now() + self.retry_policy.deadline;
+        let mut retry = 0;
+        loop {
+            let err = match self.try_fetch_json(url.clone()).await {
+                Ok(value) => return Ok(value),
+                Err(err) if err.is_transient() => err,
+                Err(err) => return Err(err),
+            };
+
+            retry += 1;
+            let delay = self.retry_policy.backoff(retry);
+            if Instant::now() + delay > deadline {
+                tracing::warn!(%err, retry, "giving up on request to MangaDex");
+                return Err(err);
+            }
+
+            tracing::warn!(%err, retry, ?delay, "request to MangaDex failed, retrying");
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Makes a single attempt at sending an HTTP GET request to a given url decoding the
+    /// response, if successful, from JSON.
+    async fn try_fetch_json<T>(&self, url: Url) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.rate_limiter.acquire().await;
+        let endpoint = self.endpoint_label(&url);
+        let started = Instant::now();
+        let resp = self.http.get(url.clone()).send().await;
+        let status_label = match &resp {
+            Ok(resp) => resp.status().as_str().to_owned(),
+            Err(_) => String::from("error"),
+        };
+        metrics::histogram!(
+            "mangadex_bot_mangadex_request_duration_seconds",
+            started.elapsed(),
+            "endpoint" => endpoint.clone(),
+            "status" => status_label.clone(),
+        );
+        metrics::increment_counter!(
+            "mangadex_bot_mangadex_requests_total",
+            "endpoint" => endpoint,
+            "status" => status_label,
+        );
+
+        let resp = resp
+            .map_err(|err| err.with_url(url.clone()))
+            .map_err(network_error)?;
+        let status = resp.status();
+        self.rate_limiter.update(status, resp.headers()).await;
+
+        let body = resp
+            .text()
+            .await
+            .map_err(|err| err.with_url(url))
+            .map_err(network_error)?;
+        if !status.is_success() {
+            return Err(status_error(status, &body));
+        }
+
+        serde_json::from_str(&body).map_err(|source| Error::Decode {
+            source,
+            body: body.chars().take(BODY_SNIPPET_LENGTH).collect(),
+        })
+    }
+}
+
+/// Converts a [reqwest::Error] into a [crate::mangadex::Error].
+#[tracing::instrument(level = "error")]
+fn network_error(err: reqwest::Error) -> Error {
+    if err.is_timeout() {
+        Error::Timeout(err)
+    } else if err.is_connect() {
+        Error::Connection(err)
+    } else {
+        Error::Network(err)
+    }
+}
+
+/// Converts an unsuccessful response into a [crate::mangadex::Error] based on its status code,
+/// including any errors listed in the body.
+#[tracing::instrument(level = "error", skip(body))]
+fn status_error(status: StatusCode, body: &str) -> Error {
+    let errors = serde_json::from_str::<ErrorResponse>(body)
+        .map(|r| r.errors)
+        .unwrap_or_default();
+
+    match status {
+        StatusCode::NOT_FOUND => Error::NotFound(errors),
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited(errors),
+        status if status.is_server_error() => Error::Server(status, errors),
+        status => Error::Status(status, errors),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::mock::{chapter_json, manga_json, MockMangaDex};
+
+    use super::*;
+
+    const MANGA_ID: &str = "a96676e5-8ae2-425e-b549-7f15dd34a6d8";
+
+    #[tokio::test]
+    async fn english_title() {
+        let mock = MockMangaDex::start().await;
+        mock.manga(manga_json(MANGA_ID, "Komi Can't Communicate", "safe"))
+            .await;
+
+        let title = mock.client().english_title(MANGA_ID).await.unwrap();
+        assert_eq!(title.as_deref(), Some("Komi Can't Communicate"));
+    }
+
+    #[tokio::test]
+    async fn english_title_falls_back_to_romanized_title() {
+        let mock = MockMangaDex::start().await;
+        let mut manga = manga_json(MANGA_ID, "", "safe");
+        manga["attributes"]["title"] = serde_json::json!({ "ja-ro": "Komi-san wa Komyushou desu" });
+        mock.manga(manga).await;
+
+        let title = mock.client().english_title(MANGA_ID).await.unwrap();
+        assert_eq!(title.as_deref(), Some("Komi-san wa Komyushou desu"));
+    }
+
+    #[tokio::test]
+    async fn english_title_not_found() {
+        let mock = MockMangaDex::start().await;
+        mock.manga_error(MANGA_ID, 404, "Manga could not be found")
+            .await;
+
+        let err = mock.client().english_title(MANGA_ID).await.unwrap_err();
+        match err {
+            Error::NotFound(errors) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].status, 404);
+                assert_eq!(
+                    errors[0].detail.as_deref(),
+                    Some("Manga could not be found")
+                );
+            }
+            err => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn english_title_error_envelope() {
+        let mock = MockMangaDex::start().await;
+        let body = r#"{"result":"error","errors":[{"id":"1","status":400,"title":"Bad request"}]}"#;
+        mock.manga_raw(MANGA_ID, 200, body).await;
+
+        let err = mock.client().english_title(MANGA_ID).await.unwrap_err();
+        match err {
+            Error::Api(errors) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].title, "Bad request");
+                assert_eq!(errors[0].detail, None);
+            }
+            err => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn english_title_server_error() {
+        let mock = MockMangaDex::start().await;
+        mock.manga_raw(MANGA_ID, 503, "Service Unavailable").await;
+
+        let err = mock.client().english_title(MANGA_ID).await.unwrap_err();
+        match err {
+            Error::Server(status, errors) => {
+                assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+                assert!(errors.is_empty());
+            }
+            err => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn english_title_rate_limited() {
+        let mock = MockMangaDex::start().await;
+        mock.manga_error(MANGA_ID, 429, "Too many requests").await;
+
+        let err = mock.client().english_title(MANGA_ID).await.unwrap_err();
+        assert!(matches!(err, Error::RateLimited(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn english_title_decode_error() {
+        let mock = MockMangaDex::start().await;
+        let body = format!("<html>{}</html>", "x".repeat(1000));
+        mock.manga_raw(MANGA_ID, 200, &body).await;
+
+        let err = mock.client().english_title(MANGA_ID).await.unwrap_err();
+        match err {
+            Error::Decode { body, .. } => {
+                assert!(body.starts_with("<html>"));
+                assert_eq!(body.len(), BODY_SNIPPET_LENGTH);
+            }
+            err => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_error() {
+        // Nothing listens on the discard port.
+        let base_url = Url::parse("http://127.0.0.1:9").unwrap();
+        let client = MangaDexClient::new(base_url, Duration::from_secs(5))
+            .unwrap()
+            .with_retry_policy(RetryPolicy::new(Duration::ZERO));
+
+        let err = client.english_title(MANGA_ID).await.unwrap_err();
+        assert!(matches!(err, Error::Connection(_)), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let mock = MockMangaDex::start().await;
+        mock.manga_failures(MANGA_ID, 503, 2).await;
+        mock.manga(manga_json(MANGA_ID, "Komi Can't Communicate", "safe"))
+            .await;
+
+        let title = mock.client().english_title(MANGA_ID).await.unwrap();
+        assert_eq!(title.as_deref(), Some("Komi Can't Communicate"));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let mock = MockMangaDex::start().await;
+        mock.manga_failures(MANGA_ID, 400, 1).await;
+        mock.manga(manga_json(MANGA_ID, "Komi Can't Communicate", "safe"))
+            .await;
+
+        let err = mock.client().english_title(MANGA_ID).await.unwrap_err();
+        assert!(
+            matches!(err, Error::Status(StatusCode::BAD_REQUEST, _)),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn endpoint_label() {
+        let base_url = Url::parse("http://localhost:1234/api").unwrap();
+        let client = MangaDexClient::new(base_url, Duration::from_secs(5)).unwrap();
+
+        let url = client.endpoint(&format!("manga/{MANGA_ID}"));
+        assert_eq!(client.endpoint_label(&url), "manga/{id}");
+
+        let url = client.endpoint("chapter?manga=1");
+        assert_eq!(client.endpoint_label(&url), "chapter");
+    }
+
+    #[tokio::test]
+    async fn latest_chapter() {
+        let mock = MockMangaDex::start().await;
+        let chapter = chapter_json("ch-2", MANGA_ID, "2", "en", "2023-01-02T00:00:00+00:00");
+        mock.latest_chapter(MANGA_ID, "en", vec![chapter]).await;
+        mock.latest_chapter(MANGA_ID, "fr", vec![]).await;
+
+        let client = mock.client();
+        let chapter = client
+            .latest_chapter(MANGA_ID, "en", &ContentRating::DEFAULT)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(chapter.id, "ch-2");
+        assert_eq!(chapter.attributes.chapter.as_deref(), Some("2"));
+        assert_eq!(chapter.manga_id(), Some(MANGA_ID));
+
+        let chapter = client
+            .latest_chapter(MANGA_ID, "fr", &ContentRating::DEFAULT)
+            .await
+            .unwrap();
+        assert!(chapter.is_none());
+    }
+
+    #[tokio::test]
+    async fn chapters_published_since_splits_windows_too_large_to_page_through() {
+        let mock = MockMangaDex::start().await;
+        let mut first: Vec<_> = (0..100)
+            .map(|i| {
+                let at = "2023-01-02T00:00:00+00:00";
+                chapter_json(&format!("ch-{i}"), MANGA_ID, &i.to_string(), "en", at)
+            })
+            .collect();
+        first[99]["attributes"]["publishAt"] = "2023-01-03T00:00:00+00:00".into();
+        mock.chapter_pages("2023-01-01T00:00:00", 20_000, first.clone())
+            .await;
+        // The next window starts with the last chapter seen.
+        let last = chapter_json("ch-100", MANGA_ID, "100", "en", "2023-01-04T00:00:00+00:00");
+        mock.chapter_pages("2023-01-03T00:00:00", 2, vec![first[99].clone(), last])
+            .await;
+
+        let chapters = mock
+            .client()
+            .with_rate_limit(1000, Duration::from_secs(1))
+            .chapters_published_since(&[MANGA_ID], &["en"], datetime!(2023-01-01 00:00 UTC))
+            .await
+            .unwrap();
+
+        let ids: Vec<_> = chapters.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids.len(), 101);
+        assert_eq!(ids[99], "ch-99");
+        assert_eq!(ids[100], "ch-100");
+    }
+
+    #[tokio::test]
+    async fn chapters_published_since_fails_if_window_cannot_be_split() {
+        let mock = MockMangaDex::start().await;
+        let page: Vec<_> = (0..100)
+            .map(|i| {
+                let at = "2023-01-01T00:00:00+00:00";
+                chapter_json(&format!("ch-{i}"), MANGA_ID, &i.to_string(), "en", at)
+            })
+            .collect();
+        mock.chapter_pages("2023-01-01T00:00:00", 20_000, page)
+            .await;
+
+        let err = mock
+            .client()
+            .with_rate_limit(1000, Duration::from_secs(1))
+            .chapters_published_since(&[MANGA_ID], &["en"], datetime!(2023-01-01 00:00 UTC))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::TooManyResults(20_000)), "{err:?}");
+    }
+}
@@ -0,0 +1,285 @@
+//! A client-side rate limiter for requests made to the MangaDex API.
+//!
+//! MangaDex enforces a global limit of 5 requests per second per IP address along with
+//! stricter limits on some endpoints. Requests are throttled using a token bucket sized to
+//! the global limit, and any rate limit headers returned by the API are honored on top of
+//! that by blocking all requests until the API says it is safe to continue.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::{header::HeaderMap, StatusCode};
+use tokio::{sync::Mutex, time::Instant};
+
+/// Header containing the number of requests left in the current rate limit window.
+const REMAINING_HEADER: &str = "X-RateLimit-Remaining";
+
+/// Header containing the unix timestamp at which the current rate limit window ends.
+const RETRY_AFTER_TIMESTAMP_HEADER: &str = "X-RateLimit-Retry-After";
+
+/// Standard header containing the number of seconds to wait after a `429` response.
+const RETRY_AFTER_HEADER: &str = "Retry-After";
+
+/// How long to block requests for when the API reports that it is rate limiting us but does
+/// not say for how long.
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A token bucket rate limiter shared by all requests made to the MangaDex API.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// The maximum number of tokens the bucket can hold.
+    capacity: f64,
+    /// The time it takes to refill a single token.
+    refill_interval: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    tokens: f64,
+    last_refill: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter that allows at most `requests` requests every `period`.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        Self {
+            capacity: requests as f64,
+            refill_interval: period / requests,
+            state: Mutex::new(State {
+                tokens: requests as f64,
+                last_refill: Instant::now(),
+                blocked_until: None,
+            }),
+        }
+    }
+
+    /// Creates a rate limiter matching MangaDex's published global limit.
+    pub fn mangadex() -> Self {
+        Self::new(5, Duration::from_secs(1))
+    }
+
+    /// Waits until a request is allowed to be sent.
+    ///
+    /// The lock on the bucket's state isn't held while sleeping, so responses can update the
+    /// limiter in the meantime. Each time a waiter wakes it checks the state again, only
+    /// taking a token once it isn't blocked and one is available.
+    pub async fn acquire(&self) {
+        loop {
+            let wake_at = {
+                let mut state = self.state.lock().await;
+                self.refill(&mut state);
+                let now = state.last_refill;
+
+                match state.blocked_until {
+                    Some(blocked_until) if blocked_until > now => {
+                        tracing::debug!(wait = ?blocked_until - now, "waiting for rate limit window to reset");
+                        blocked_until
+                    }
+                    _ => {
+                        state.blocked_until = None;
+                        if state.tokens >= 1.0 {
+                            state.tokens -= 1.0;
+                            return;
+                        }
+                        now + self.refill_interval.mul_f64(1.0 - state.tokens)
+                    }
+                }
+            };
+
+            tokio::time::sleep_until(wake_at).await;
+        }
+    }
+
+    /// Updates the limiter using the rate limit information returned in a response.
+    pub async fn update(&self, status: StatusCode, headers: &HeaderMap) {
+        let remaining = header_value::<u64>(headers, REMAINING_HEADER);
+        let limited = status == StatusCode::TOO_MANY_REQUESTS || remaining == Some(0);
+        if !limited {
+            return;
+        }
+
+        let wait = header_value::<u64>(headers, RETRY_AFTER_TIMESTAMP_HEADER)
+            .map(until_timestamp)
+            .or_else(|| header_value::<u64>(headers, RETRY_AFTER_HEADER).map(Duration::from_secs))
+            .unwrap_or(DEFAULT_BACKOFF);
+
+        tracing::warn!(%status, ?remaining, ?wait, "MangaDex rate limit reached");
+
+        let blocked_until = Instant::now() + wait;
+        let mut state = self.state.lock().await;
+        self.refill(&mut state);
+        state.tokens = 0.0;
+        state.blocked_until = Some(
+            state
+                .blocked_until
+                .map_or(blocked_until, |b| b.max(blocked_until)),
+        );
+    }
+
+    /// Adds any tokens accumulated since the last refill to the bucket.
+    fn refill(&self, state: &mut State) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill);
+        let tokens = elapsed.as_secs_f64() / self.refill_interval.as_secs_f64();
+        state.tokens = (state.tokens + tokens).min(self.capacity);
+        state.last_refill = now;
+    }
+}
+
+/// Parses the value of a header, returning `None` if it is missing or malformed.
+fn header_value<T: std::str::FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Computes the time remaining until a given unix timestamp (in seconds).
+fn until_timestamp(timestamp: u64) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Duration::from_secs(timestamp).saturating_sub(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::header::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|&(name, value)| {
+                let name: HeaderName = name.parse().unwrap();
+                (name, HeaderValue::from_str(value).unwrap())
+            })
+            .collect()
+    }
+
+    /// Acquires a token, returning how long it took.
+    async fn timed_acquire(limiter: &RateLimiter) -> Duration {
+        let start = Instant::now();
+        limiter.acquire().await;
+        start.elapsed()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_allows_bursts_up_to_capacity() {
+        let limiter = RateLimiter::new(5, Duration::from_secs(1));
+
+        for _ in 0..5 {
+            assert_eq!(timed_acquire(&limiter).await, Duration::ZERO);
+        }
+        assert_eq!(timed_acquire(&limiter).await, Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_refills_tokens_over_time() {
+        let limiter = RateLimiter::new(5, Duration::from_secs(1));
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+
+        assert_eq!(timed_acquire(&limiter).await, Duration::ZERO);
+        assert_eq!(timed_acquire(&limiter).await, Duration::ZERO);
+        assert_eq!(timed_acquire(&limiter).await, Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_does_not_hold_lock_while_waiting() {
+        let limiter = Arc::new(RateLimiter::new(1, Duration::from_secs(1)));
+        limiter.acquire().await;
+        let waiter = tokio::spawn({
+            let limiter = Arc::clone(&limiter);
+            async move { timed_acquire(&limiter).await }
+        });
+        tokio::task::yield_now().await;
+
+        let start = Instant::now();
+        limiter.update(StatusCode::OK, &HeaderMap::new()).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        assert_eq!(waiter.await.unwrap(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn update_blocks_callers_already_waiting() {
+        let limiter = Arc::new(RateLimiter::new(1, Duration::from_secs(1)));
+        limiter.acquire().await;
+        let waiter = tokio::spawn({
+            let limiter = Arc::clone(&limiter);
+            async move { timed_acquire(&limiter).await }
+        });
+        tokio::task::yield_now().await;
+
+        limiter
+            .update(
+                StatusCode::TOO_MANY_REQUESTS,
+                &headers(&[(RETRY_AFTER_HEADER, "3")]),
+            )
+            .await;
+
+        assert_eq!(waiter.await.unwrap(), Duration::from_secs(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn update_blocks_until_rate_limit_window_resets() {
+        let limiter = RateLimiter::new(5, Duration::from_secs(1));
+        let reset = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + Duration::from_secs(3);
+        let headers = headers(&[
+            (REMAINING_HEADER, "0"),
+            (RETRY_AFTER_TIMESTAMP_HEADER, &reset.as_secs().to_string()),
+        ]);
+
+        limiter.update(StatusCode::OK, &headers).await;
+
+        // The timestamp only has second precision.
+        let wait = timed_acquire(&limiter).await;
+        assert!(wait > Duration::from_secs(2), "{wait:?}");
+        assert!(wait <= Duration::from_secs(3), "{wait:?}");
+        assert_eq!(timed_acquire(&limiter).await, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn update_honors_retry_after_on_too_many_requests() {
+        let limiter = RateLimiter::new(5, Duration::from_secs(1));
+
+        limiter
+            .update(
+                StatusCode::TOO_MANY_REQUESTS,
+                &headers(&[(RETRY_AFTER_HEADER, "3")]),
+            )
+            .await;
+
+        assert_eq!(timed_acquire(&limiter).await, Duration::from_secs(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn update_backs_off_on_too_many_requests_without_headers() {
+        let limiter = RateLimiter::new(5, Duration::from_secs(1));
+
+        limiter
+            .update(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new())
+            .await;
+
+        assert_eq!(timed_acquire(&limiter).await, DEFAULT_BACKOFF);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn update_ignores_responses_with_requests_remaining() {
+        let limiter = RateLimiter::new(5, Duration::from_secs(1));
+
+        limiter
+            .update(
+                StatusCode::OK,
+                &headers(&[(REMAINING_HEADER, "4"), (RETRY_AFTER_HEADER, "3")]),
+            )
+            .await;
+
+        assert_eq!(timed_acquire(&limiter).await, Duration::ZERO);
+    }
+}
@@ -0,0 +1,65 @@
+//! Retrying of requests made to the MangaDex API that fail for transient reasons.
+//!
+//! Failed requests are retried after an exponentially increasing, jittered delay until a
+//! deadline for the request as a whole passes.
+
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Determines how often and for how long failed requests are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The delay before the first retry.
+    pub initial_backoff: Duration,
+    /// The maximum delay between two attempts.
+    pub max_backoff: Duration,
+    /// How long after the first attempt to stop retrying.
+    pub deadline: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy that keeps retrying until a given deadline.
+    pub fn new(deadline: Duration) -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            deadline,
+        }
+    }
+
+    /// Computes the delay before a given retry (starting at 1).
+    ///
+    /// The delay doubles with each retry up to the maximum backoff, and is then randomized to
+    /// somewhere between half of that and all of it so that concurrent requests don't retry in
+    /// lockstep.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+
+        let half = backoff / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_is_bounded() {
+        let policy = RetryPolicy::new(Duration::from_secs(60));
+        assert!(policy.backoff(1) >= Duration::from_millis(250));
+        assert!(policy.backoff(1) <= Duration::from_millis(500));
+        assert!(policy.backoff(20) >= Duration::from_secs(15));
+        assert!(policy.backoff(20) <= Duration::from_secs(30));
+    }
+}
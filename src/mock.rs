@@ -0,0 +1,475 @@
+//! The `mock` module contains an offline stand-in for the MangaDex API (and the parts of the
+//! Discord API used to announce chapters) for use in tests, along with storage that fails on
+//! demand.
+//!
+//! Responses are scripted per test by mounting them on a local HTTP server.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use reqwest::Url;
+use serde_json::{json, Value};
+use serenity::{
+    async_trait,
+    http::{Http, HttpBuilder},
+    model::prelude::{ChannelId, GuildId},
+};
+use wiremock::{
+    http::Method,
+    matchers::{method, path, path_regex, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::db::{
+    self, ChapterMarker, GuildSettings, Manga, MemoryStore, Notification, NotificationStatus,
+    Store, Subscriber, Subscription,
+};
+use crate::mangadex::{MangaDexClient, RetryPolicy};
+
+/// A local HTTP server standing in for the MangaDex API.
+pub struct MockMangaDex {
+    server: MockServer,
+}
+
+impl MockMangaDex {
+    /// Starts a new server with no scripted responses.
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// Creates a client that sends its requests to this server.
+    ///
+    /// Failed requests are retried with much shorter delays than in production to keep tests
+    /// fast.
+    pub fn client(&self) -> MangaDexClient {
+        let base_url = Url::parse(&self.server.uri()).unwrap();
+        MangaDexClient::new(base_url, Duration::from_secs(5))
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(10),
+                deadline: Duration::from_millis(100),
+            })
+    }
+
+    /// Responds to the next `count` requests for a manga with a given status code, before
+    /// any other scripted responses.
+    pub async fn manga_failures(&self, manga_id: &str, status: u16, count: u64) {
+        Mock::given(method("GET"))
+            .and(path(format!("/manga/{manga_id}")))
+            .respond_with(ResponseTemplate::new(status))
+            .up_to_n_times(count)
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Responds to requests for a manga with a given entity.
+    pub async fn manga(&self, manga: Value) {
+        let manga_id = manga["id"].as_str().unwrap().to_owned();
+        Mock::given(method("GET"))
+            .and(path(format!("/manga/{manga_id}")))
+            .respond_with(ok_entity(manga))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Responds to requests for a manga with an error envelope.
+    pub async fn manga_error(&self, manga_id: &str, status: u16, detail: &str) {
+        Mock::given(method("GET"))
+            .and(path(format!("/manga/{manga_id}")))
+            .respond_with(error(status, detail))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Responds to requests for a manga with a given status code and raw body.
+    pub async fn manga_raw(&self, manga_id: &str, status: u16, body: &str) {
+        Mock::given(method("GET"))
+            .and(path(format!("/manga/{manga_id}")))
+            .respond_with(ResponseTemplate::new(status).set_body_string(body))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Responds to requests for the latest chapter of a manga in a given language.
+    pub async fn latest_chapter(&self, manga_id: &str, language: &str, chapters: Vec<Value>) {
+        Mock::given(method("GET"))
+            .and(path("/chapter"))
+            .and(query_param("manga", manga_id))
+            .and(query_param("translatedLanguage[]", language))
+            .respond_with(ok_collection(chapters))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Responds to every page of chapters published since a given time, as formatted in the
+    /// request, with the same chapters while claiming there are `total` in all.
+    pub async fn chapter_pages(&self, since: &str, total: usize, chapters: Vec<Value>) {
+        Mock::given(method("GET"))
+            .and(path("/chapter"))
+            .and(query_param("publishAtSince", since))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "result": "ok",
+                "response": "collection",
+                "limit": 100,
+                "offset": 0,
+                "total": total,
+                "data": chapters,
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Responds to requests for the chapters of many manga published since some time.
+    pub async fn chapters_published_since(&self, manga_id: &str, chapters: Vec<Value>) {
+        Mock::given(method("GET"))
+            .and(path("/chapter"))
+            .and(query_param("manga[]", manga_id))
+            .respond_with(ok_collection(chapters))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// A local HTTP server standing in for the Discord API.
+pub struct MockDiscord {
+    server: MockServer,
+}
+
+impl MockDiscord {
+    /// Starts a new server with no scripted responses.
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// Creates a client that sends its requests to this server.
+    pub fn http(&self) -> Http {
+        HttpBuilder::new("Bot token")
+            .proxy(self.server.uri())
+            .unwrap()
+            .ratelimiter_disabled(true)
+            .build()
+    }
+
+    /// Accepts messages sent to a given channel, expecting exactly `count` of them by the
+    /// time the server is dropped.
+    pub async fn expect_messages(&self, channel_id: u64, count: u64) {
+        Mock::given(method("POST"))
+            .and(path_regex(format!(
+                r"^/api/v\d+/channels/{channel_id}/messages$"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_json(message_json(channel_id)))
+            .expect(count)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Gets the bodies of the messages sent to a given channel so far, in the order they were
+    /// sent.
+    pub async fn sent_messages(&self, channel_id: u64) -> Vec<Value> {
+        let suffix = format!("/channels/{channel_id}/messages");
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.method == Method::Post && r.url.path().ends_with(&suffix))
+            .map(|r| r.body_json().unwrap())
+            .collect()
+    }
+
+    /// Responds to requests for a text channel with a given id in a given guild.
+    pub async fn channel(&self, channel_id: u64, guild_id: u64) {
+        Mock::given(method("GET"))
+            .and(path_regex(format!(r"^/api/v\d+/channels/{channel_id}$")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": channel_id.to_string(),
+                "type": 0,
+                "guild_id": guild_id.to_string(),
+                "name": "manga",
+                "position": 0,
+                "permission_overwrites": [],
+                "nsfw": false,
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Rejects every message sent to a given channel with a given status and JSON error code.
+    pub async fn reject_messages(&self, channel_id: u64, status: u16, code: isize) {
+        Mock::given(method("POST"))
+            .and(path_regex(format!(
+                r"^/api/v\d+/channels/{channel_id}/messages$"
+            )))
+            .respond_with(ResponseTemplate::new(status).set_body_json(json!({
+                "code": code,
+                "message": "Error",
+            })))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// Storage that keeps everything in memory like [MemoryStore] but queues notifications slowly
+/// or not at all.
+#[derive(Debug, Default)]
+pub struct MockOutbox {
+    store: MemoryStore,
+    /// Whether queuing notifications fails.
+    broken: bool,
+    /// How long queuing notifications takes.
+    delay: Duration,
+    /// The number of calls queuing notifications right now.
+    in_flight: AtomicUsize,
+    /// The most calls queuing notifications there have been at once.
+    peak: AtomicUsize,
+}
+
+impl MockOutbox {
+    /// Creates storage that fails to queue notifications.
+    pub fn broken() -> Self {
+        Self {
+            broken: true,
+            ..Self::default()
+        }
+    }
+
+    /// Creates storage that takes a given time to queue notifications.
+    pub fn slow(delay: Duration) -> Self {
+        Self {
+            delay,
+            ..Self::default()
+        }
+    }
+
+    /// The most calls queuing notifications there have been at once.
+    pub fn peak_enqueues(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Store for MockOutbox {
+    async fn read_manga(&self, manga_id: &str) -> db::Result<Option<Manga>> {
+        self.store.read_manga(manga_id).await
+    }
+
+    async fn read_all_manga(&self) -> db::Result<Vec<Manga>> {
+        self.store.read_all_manga().await
+    }
+
+    async fn read_subscribed_manga(&self, subscriber: Subscriber) -> db::Result<Vec<Manga>> {
+        self.store.read_subscribed_manga(subscriber).await
+    }
+
+    async fn create_manga(&self, manga: Manga) -> db::Result<()> {
+        self.store.create_manga(manga).await
+    }
+
+    async fn delete_manga(&self, manga_id: &str) -> db::Result<()> {
+        self.store.delete_manga(manga_id).await
+    }
+
+    async fn set_subscriptions(
+        &self,
+        manga_id: &str,
+        subscriptions: &[Subscription],
+    ) -> db::Result<()> {
+        self.store.set_subscriptions(manga_id, subscriptions).await
+    }
+
+    async fn set_subscription_guild(
+        &self,
+        manga_id: &str,
+        channel_id: ChannelId,
+        guild_id: GuildId,
+    ) -> db::Result<()> {
+        self.store
+            .set_subscription_guild(manga_id, channel_id, guild_id)
+            .await
+    }
+
+    async fn set_latest_chapter(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+    ) -> db::Result<()> {
+        self.store
+            .set_latest_chapter(manga_id, language, marker)
+            .await
+    }
+
+    async fn read_guild_settings(&self, guild_id: GuildId) -> db::Result<GuildSettings> {
+        self.store.read_guild_settings(guild_id).await
+    }
+
+    async fn read_all_guild_settings(&self) -> db::Result<Vec<GuildSettings>> {
+        self.store.read_all_guild_settings().await
+    }
+
+    async fn save_guild_settings(&self, settings: GuildSettings) -> db::Result<()> {
+        self.store.save_guild_settings(settings).await
+    }
+
+    async fn ping(&self) -> db::Result<()> {
+        self.store.ping().await
+    }
+
+    async fn enqueue_notifications(
+        &self,
+        manga_id: &str,
+        language: &str,
+        marker: &ChapterMarker,
+        notifications: &[Notification],
+    ) -> db::Result<()> {
+        if self.broken {
+            return Err("the outbox is broken".into());
+        }
+
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        self.store
+            .enqueue_notifications(manga_id, language, marker, notifications)
+            .await
+    }
+
+    async fn read_pending_notifications(&self) -> db::Result<Vec<Notification>> {
+        self.store.read_pending_notifications().await
+    }
+
+    async fn claim_notification(&self, id: &str, attempts: u32) -> db::Result<bool> {
+        self.store.claim_notification(id, attempts).await
+    }
+
+    async fn set_notification_status(
+        &self,
+        id: &str,
+        status: NotificationStatus,
+        attempts: u32,
+    ) -> db::Result<()> {
+        self.store
+            .set_notification_status(id, status, attempts)
+            .await
+    }
+
+    async fn fail_interrupted_notifications(&self) -> db::Result<u64> {
+        self.store.fail_interrupted_notifications().await
+    }
+
+    async fn prune_notifications(&self, before: i64) -> db::Result<()> {
+        self.store.prune_notifications(before).await
+    }
+}
+
+/// Builds a manga entity with a given id, English title and content rating.
+pub fn manga_json(id: &str, title: &str, content_rating: &str) -> Value {
+    json!({
+        "id": id,
+        "type": "manga",
+        "attributes": {
+            "title": { "en": title },
+            "contentRating": content_rating,
+        },
+        "relationships": [],
+    })
+}
+
+/// Builds a chapter entity for a given manga with the manga included as a relationship.
+pub fn chapter_json(id: &str, manga_id: &str, chapter: &str, language: &str, at: &str) -> Value {
+    json!({
+        "id": id,
+        "type": "chapter",
+        "attributes": {
+            "title": null,
+            "volume": null,
+            "chapter": chapter,
+            "pages": 20,
+            "translatedLanguage": language,
+            "createdAt": at,
+            "updatedAt": at,
+            "publishAt": at,
+            "readableAt": at,
+        },
+        "relationships": [
+            {
+                "id": manga_id,
+                "type": "manga",
+                "attributes": {
+                    "title": { "en": "Manga" },
+                    "contentRating": "safe",
+                },
+            },
+        ],
+    })
+}
+
+/// A successful response containing a single entity.
+fn ok_entity(data: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "result": "ok",
+        "response": "entity",
+        "data": data,
+    }))
+}
+
+/// A successful response containing a single page holding every entity.
+fn ok_collection(data: Vec<Value>) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "result": "ok",
+        "response": "collection",
+        "limit": 100,
+        "offset": 0,
+        "total": data.len(),
+        "data": data,
+    }))
+}
+
+/// An error envelope with a single error.
+fn error(status: u16, detail: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({
+        "result": "error",
+        "errors": [
+            {
+                "id": "00000000-0000-0000-0000-000000000000",
+                "status": status,
+                "title": "Error",
+                "detail": detail,
+            },
+        ],
+    }))
+}
+
+/// A message sent by the bot to a given channel, as returned by Discord.
+fn message_json(channel_id: u64) -> Value {
+    json!({
+        "id": "1",
+        "channel_id": channel_id.to_string(),
+        "author": {
+            "id": "2",
+            "username": "mangadex-bot",
+            "discriminator": "0000",
+            "avatar": null,
+            "bot": true,
+        },
+        "content": "",
+        "timestamp": "2023-01-01T00:00:00.000000+00:00",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "attachments": [],
+        "embeds": [],
+        "pinned": false,
+        "type": 0,
+    })
+}
@@ -1,80 +1,1494 @@
 //! The `scan` module contains functions check for new chapters.
 
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use bson::doc;
+use futures::stream::{self, StreamExt};
+use reqwest::Url;
+use serenity::builder::{CreateComponents, CreateMessage};
 use serenity::http::Http;
-use serenity::model::prelude::ChannelId;
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::prelude::{Channel, ChannelId, GuildId, Mentionable, RoleId};
+use serenity::model::Timestamp;
+use time::OffsetDateTime;
+use tokio_util::sync::CancellationToken;
 
-use crate::db::{Manga, MongoClient};
-use crate::mangadex::{self, Chapter, ChapterAttributes};
+use crate::db::{
+    AnnouncedChapter, ChapterMarker, GuildSettings, Manga, Notification, NotificationStatus, Store,
+    Subscriber, Subscription,
+};
+use crate::discord;
+use crate::health::Health;
+use crate::mangadex::{self, Chapter, ContentRating, MangaDexClient};
 
-/// An endless task that periodically scans for chapter updates.
-#[tracing::instrument(skip(http, db_client))]
-pub async fn scan(http: Arc<Http>, db_client: Arc<MongoClient>, period: Duration) {
+/// The number of times delivering a notification is attempted before giving up on it.
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+
+/// How long sent and failed notifications are kept in the outbox.
+const NOTIFICATION_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How far back the first scan after starting looks for chapters published while the bot
+/// wasn't running.
+const MAX_CATCH_UP: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Configuration for the scan task.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The period between scans.
+    pub period: Duration,
+    /// The number of new chapters for a single manga above which they are announced with a
+    /// single summary message rather than one message per chapter.
+    pub bulk_threshold: usize,
+    /// The maximum number of manga checked, and of subscribers delivered to, at once.
+    pub concurrency: usize,
+}
+
+/// A task that periodically scans for chapter updates until `shutdown` is cancelled.
+///
+/// Notifications left in the outbox by a previous run are delivered by the first scan.
+#[tracing::instrument(skip(http, db_client, mangadex, health, shutdown))]
+pub async fn scan<S: Store>(
+    http: Arc<Http>,
+    db_client: Arc<S>,
+    mangadex: Arc<MangaDexClient>,
+    health: Arc<Health>,
+    config: Config,
+    shutdown: CancellationToken,
+) {
+    // Each subscriber's notifications are delivered one at a time, so at most one per
+    // subscriber can have been in flight when the bot stopped. Whether it was delivered is
+    // unknown, and announcing a chapter twice is worse than missing it, so it's given up on.
+    // Those still pending are delivered by the first scan.
+    match db_client.fail_interrupted_notifications().await {
+        Ok(0) | Err(_) => {}
+        Ok(count) => tracing::warn!(count, "gave up on notifications interrupted by a restart"),
+    }
+
+    let mut since = initial_since(db_client.as_ref(), &config).await;
     loop {
-        let _ = check_for_updates(&http, &db_client).await;
-        tokio::time::sleep(period).await;
+        let started = OffsetDateTime::now_utc();
+        let timer = Instant::now();
+        let result = check_for_updates(
+            &http,
+            db_client.as_ref(),
+            &mangadex,
+            &config,
+            since,
+            &shutdown,
+        )
+        .await;
+        metrics::histogram!("mangadex_bot_scan_duration_seconds", timer.elapsed());
+        if result.is_ok() {
+            since = started;
+            health.scan_succeeded();
+        }
+
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(config.period) => {}
+        }
     }
+
+    tracing::info!("scanning stopped");
 }
 
-/// For each manga in the database, queries MangaDex to see if any of them have new chapters.
-#[tracing::instrument(err, skip_all)]
+/// Works out when the first scan should look for chapters from, so that chapters published
+/// while the bot wasn't running are still announced.
+///
+/// There's no record of when the last scan happened, but no chapter published since the
+/// oldest latest chapter of any tracked manga has been announced yet. The scan goes back at
+/// most [MAX_CATCH_UP], and at least a full period.
+async fn initial_since(db_client: &impl Store, config: &Config) -> OffsetDateTime {
+    let now = OffsetDateTime::now_utc();
+    let latest = now - config.period;
+    let earliest = now - MAX_CATCH_UP;
+
+    let oldest = match db_client.read_all_manga().await {
+        Ok(manga) => manga
+            .iter()
+            .flat_map(|m| m.latest_chapters.values())
+            .filter_map(|m| mangadex::parse_timestamp(m.publish_at.as_deref()?))
+            .min(),
+        Err(err) => {
+            tracing::warn!(%err, "failed to read when the latest chapters were published");
+            None
+        }
+    };
+
+    oldest.map_or(latest, |oldest| oldest.max(earliest).min(latest))
+}
+
+/// Queries MangaDex for any chapters published since a given time for the manga in the
+/// database, queuing announcements of those that are new before delivering everything in
+/// the outbox.
+///
+/// Up to `config.concurrency` manga are checked at once. They share the MangaDex client and
+/// so its rate limit.
+///
+/// Fails if the announcements for any manga couldn't be queued, after delivering those that
+/// were, so that the next scan covers the same chapters again.
+///
+/// If `shutdown` is cancelled, the remaining manga are skipped once the updates for those
+/// being checked have been queued, and the remaining notifications are left in the outbox.
+#[tracing::instrument(err, skip(http, db_client, mangadex, shutdown))]
 async fn check_for_updates(
     http: &Http,
-    db_client: &MongoClient,
+    db_client: &impl Store,
+    mangadex: &MangaDexClient,
+    config: &Config,
+    since: OffsetDateTime,
+    shutdown: &CancellationToken,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    for manga in db_client.read_many::<Manga>(doc! {}).await? {
-        if let Ok(Some(chapter)) = mangadex::latest_chapter(&manga.id).await {
-            if Some(chapter.id.as_str()) != manga.latest_chapter_id.as_deref() {
-                for channel in manga.channels.as_slice() {
-                    // Ignore errors related to sending a message since there's not much we can do.
-                    // TODO: One potential error may be that the channel does not exist. In that
-                    //  case, we should remove the channel and all tracked manga.
-                    let _ = send_update_message(http, &manga, &chapter, *channel).await;
+    let mut manga = db_client.read_all_manga().await?;
+    backfill_guild_ids(http, db_client, &mut manga).await;
+
+    let channels: HashSet<ChannelId> = manga
+        .iter()
+        .flat_map(|m| m.subscribers.iter())
+        .filter_map(|s| match s.subscriber {
+            Subscriber::Channel(channel_id) => Some(channel_id),
+            Subscriber::User(_) => None,
+        })
+        .collect();
+    metrics::gauge!("mangadex_bot_tracked_manga", manga.len() as f64);
+    metrics::gauge!("mangadex_bot_tracked_channels", channels.len() as f64);
+
+    let manga_ids: Vec<&str> = manga.iter().map(|m| m.id.as_str()).collect();
+    let languages: BTreeSet<&str> = manga
+        .iter()
+        .flat_map(|m| m.subscribers.iter().map(|s| s.language.as_str()))
+        .collect();
+    let languages: Vec<&str> = languages.into_iter().collect();
+    let chapters = mangadex
+        .chapters_published_since(&manga_ids, &languages, since)
+        .await?;
+    let guild_settings: HashMap<GuildId, GuildSettings> = db_client
+        .read_all_guild_settings()
+        .await?
+        .into_iter()
+        .map(|s| (s.guild_id, s))
+        .collect();
+
+    let queued_at = OffsetDateTime::now_utc().unix_timestamp();
+    let position = AtomicU32::new(0);
+    let failures = AtomicUsize::new(0);
+    let checked = AtomicUsize::new(0);
+
+    let chapters = &chapters;
+    let guild_settings = &guild_settings;
+    let position = &position;
+    let failures = &failures;
+    let checked = &checked;
+    stream::iter(manga.iter())
+        .for_each_concurrent(config.concurrency, |manga| async move {
+            if shutdown.is_cancelled() {
+                return;
+            }
+            checked.fetch_add(1, Ordering::Relaxed);
+
+            // Fetched lazily the first time a language has new chapters. The cover art is
+            // only decoration so don't let a failure to fetch it stop the update.
+            let mut cover_url = None;
+            let mut failed = false;
+
+            let languages: BTreeSet<&str> = manga
+                .subscribers
+                .iter()
+                .map(|s| s.language.as_str())
+                .collect();
+            for language in languages {
+                let new_chapters = new_chapters(manga, language, chapters);
+                let Some(latest) = new_chapters.last() else {
+                    continue;
+                };
+
+                if cover_url.is_none() {
+                    cover_url = Some(mangadex.cover_art_url(&manga.id).await.ok().flatten());
                 }
+                let cover_url = cover_url.as_ref().and_then(|u| u.as_ref());
+
+                let announced: Vec<AnnouncedChapter> = new_chapters
+                    .iter()
+                    .map(|&c| AnnouncedChapter::from(c))
+                    .collect();
+                let batches: Vec<&[AnnouncedChapter]> = if announced.len() > config.bulk_threshold {
+                    vec![&announced]
+                } else {
+                    announced.chunks(1).collect()
+                };
 
-                let _ = db_client
-                    .update(
-                        doc! { "_id": &manga.id },
-                        doc! { "$set": { "latest_chapter_id": &chapter.id } },
+                let content_rating = latest.content_rating();
+                let mut notifications = Vec::new();
+                for subscription in manga.subscribers.iter().filter(|s| s.language == language) {
+                    if !is_content_rating_allowed(
+                        http,
+                        guild_settings,
+                        subscription,
+                        content_rating,
                     )
-                    .await;
+                    .await
+                    {
+                        tracing::debug!(
+                            ?subscription,
+                            ?content_rating,
+                            "content rating not allowed"
+                        );
+                        continue;
+                    }
+
+                    for chapters in batches.iter() {
+                        notifications.push(Notification::new(
+                            manga,
+                            subscription,
+                            chapters.to_vec(),
+                            cover_url.map(|u| u.to_string()),
+                            queued_at,
+                            position.fetch_add(1, Ordering::Relaxed),
+                        ));
+                    }
+                }
+
+                let marker = ChapterMarker {
+                    id: latest.id.clone(),
+                    number: latest.attributes.chapter.clone(),
+                    publish_at: new_chapters
+                        .iter()
+                        .filter_map(|c| c.attributes.publish_at.clone())
+                        .max(),
+                };
+                // If this fails then neither the notifications nor the new latest chapter are
+                // recorded. The scan as a whole fails so that the next one looks for chapters
+                // published since the same time and tries again.
+                failed |= db_client
+                    .enqueue_notifications(&manga.id, language, &marker, &notifications)
+                    .await
+                    .is_err();
+            }
+
+            if failed {
+                failures.fetch_add(1, Ordering::Relaxed);
+            }
+        })
+        .await;
+
+    if shutdown.is_cancelled() {
+        tracing::info!("stopping scan early to shut down");
+    }
+
+    deliver_notifications(http, db_client, config, shutdown).await?;
+
+    let _ = db_client
+        .prune_notifications(queued_at - NOTIFICATION_RETENTION.as_secs() as i64)
+        .await;
+
+    metrics::gauge!(
+        "mangadex_bot_scan_manga_checked",
+        checked.load(Ordering::Relaxed) as f64
+    );
+
+    match failures.load(Ordering::Relaxed) {
+        0 => Ok(()),
+        count => Err(format!("failed to queue announcements for {count} manga").into()),
+    }
+}
+
+/// Fills in the guild of channel subscriptions created before guilds were recorded, so that
+/// the guild's settings apply to them and they're removed along with the guild.
+///
+/// Only the guild of each subscription is written back, as the subscriptions may have been
+/// changed by commands while resolving channels. Subscriptions whose channel can't be
+/// resolved are left as they are for the next scan to try again.
+async fn backfill_guild_ids(http: &Http, db_client: &impl Store, manga: &mut [Manga]) {
+    let mut guilds: HashMap<ChannelId, Option<GuildId>> = HashMap::new();
+
+    for manga in manga.iter_mut() {
+        for subscription in manga.subscribers.iter_mut() {
+            let Subscriber::Channel(channel_id) = subscription.subscriber else {
+                continue;
+            };
+            if subscription.guild_id.is_some() {
+                continue;
+            }
+
+            let guild_id = match guilds.get(&channel_id) {
+                Some(guild_id) => *guild_id,
+                None => {
+                    let guild_id = match channel_id.to_channel(http).await {
+                        Ok(Channel::Guild(channel)) => Some(channel.guild_id),
+                        Ok(_) => None,
+                        Err(err) => {
+                            tracing::warn!(%err, %channel_id, "failed to resolve guild of channel");
+                            None
+                        }
+                    };
+                    guilds.insert(channel_id, guild_id);
+                    guild_id
+                }
+            };
+
+            let Some(guild_id) = guild_id else {
+                continue;
+            };
+            subscription.guild_id = Some(guild_id);
+            tracing::info!(manga = manga.id, %channel_id, %guild_id, "recording guild of legacy subscription");
+            if let Err(err) = db_client
+                .set_subscription_guild(&manga.id, channel_id, guild_id)
+                .await
+            {
+                tracing::warn!(%err, manga = manga.id, %channel_id, "failed to record guild of subscription");
+            }
+        }
+    }
+}
+
+/// Delivers the notifications waiting in the outbox.
+///
+/// Notifications are delivered to up to `config.concurrency` subscribers at once, and to
+/// each subscriber in the order they were queued. Messages are all sent with the same
+/// client, so serenity's per-route rate limits still apply. Notifications for subscriptions
+/// that have since been removed are marked as failed instead.
+///
+/// If `shutdown` is cancelled, the remaining notifications are left for the next scan.
+#[tracing::instrument(err, skip_all)]
+async fn deliver_notifications(
+    http: &Http,
+    db_client: &impl Store,
+    config: &Config,
+    shutdown: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let notifications = db_client.read_pending_notifications().await?;
+    let subscriptions: HashSet<(String, Subscriber)> = db_client
+        .read_all_manga()
+        .await?
+        .into_iter()
+        .flat_map(|m| {
+            m.subscribers
+                .into_iter()
+                .map(move |s| (m.id.clone(), s.subscriber))
+        })
+        .collect();
+
+    let mut queues: HashMap<Subscriber, Vec<Notification>> = HashMap::new();
+    for notification in notifications {
+        // The subscriber may have stopped tracking the manga, or been removed, since the
+        // notification was queued.
+        if !subscriptions.contains(&(notification.manga_id.clone(), notification.subscriber)) {
+            tracing::info!(
+                notification = notification.id,
+                "dropping notification for a subscription that no longer exists"
+            );
+            db_client
+                .set_notification_status(
+                    &notification.id,
+                    NotificationStatus::Failed,
+                    notification.attempts,
+                )
+                .await?;
+            continue;
+        }
+
+        queues
+            .entry(notification.subscriber)
+            .or_default()
+            .push(notification);
+    }
+
+    let undeliverable: Vec<(Subscriber, Vec<Notification>)> = stream::iter(queues)
+        .map(|(subscriber, queue)| async move {
+            let reachable = deliver_queue(http, db_client, &queue, shutdown).await;
+            (subscriber, queue, reachable)
+        })
+        .buffer_unordered(config.concurrency)
+        .filter_map(|(subscriber, queue, reachable)| async move {
+            (!reachable).then_some((subscriber, queue))
+        })
+        .collect()
+        .await;
+
+    if shutdown.is_cancelled() {
+        tracing::info!("stopping delivery early to shut down");
+    }
+
+    for (subscriber, queue) in undeliverable {
+        match subscriber {
+            // Channels which were deleted or can no longer be accessed are removed from every
+            // manga.
+            Subscriber::Channel(_) => {
+                let _ = db_client.remove_subscriber(subscriber).await;
+            }
+            // Users who can no longer be sent direct messages are unsubscribed from the manga
+            // they were being notified about.
+            Subscriber::User(_) => {
+                let manga_ids: BTreeSet<&str> = queue.iter().map(|n| n.manga_id.as_str()).collect();
+                for manga_id in manga_ids {
+                    if let Ok(Some(manga)) = db_client.read_manga(manga_id).await {
+                        let _ = db_client.remove_subscribers(&manga, &[subscriber]).await;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Delivers the notifications for a single subscriber in order, returning whether the
+/// subscriber can still be delivered to.
+///
+/// Each notification is claimed, marking it as being sent, before it is delivered, and is
+/// marked as sent or failed afterwards, so one is never delivered twice even if delivery is
+/// interrupted part way through.
+/// Notifications that fail for reasons that may be temporary are retried by later scans, up
+/// to [MAX_DELIVERY_ATTEMPTS] times, and the rest of the queue waits for them so that
+/// announcements stay in order.
+async fn deliver_queue(
+    http: &Http,
+    db_client: &impl Store,
+    queue: &[Notification],
+    shutdown: &CancellationToken,
+) -> bool {
+    for (i, notification) in queue.iter().enumerate() {
+        if shutdown.is_cancelled() {
+            return true;
+        }
+
+        // Without a record that delivery started, a crash while sending could lead to the
+        // notification being delivered again. Claiming it only succeeds if it's still pending
+        // so that it can't be delivered by anything else at the same time.
+        let attempts = notification.attempts + 1;
+        match db_client
+            .claim_notification(&notification.id, attempts)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::debug!(id = notification.id, "notification already claimed");
+                continue;
             }
+            Err(_) => return true,
         }
 
-        // Add a bit of delay between each scan in order to avoid any rate limiting put
-        // in place by MangaDex.
-        // FIXME: A better solution would be to put rate limiting on the mangadex::latest_chapter function itself.
-        tokio::time::sleep(Duration::from_millis(250)).await;
+        let result = deliver(http, notification).await;
+        let outcome = if result.is_ok() { "sent" } else { "failed" };
+        metrics::increment_counter!("mangadex_bot_notifications_total", "outcome" => outcome);
+
+        let subscriber = notification.subscriber;
+        let status = match result {
+            Ok(()) => NotificationStatus::Sent,
+            Err(err) => {
+                let unreachable = match (discord::error_code(&err), subscriber) {
+                    (Some(discord::CANNOT_MESSAGE_USER), Subscriber::User(_)) => {
+                        tracing::info!(?subscriber, "user does not accept direct messages");
+                        true
+                    }
+                    (
+                        Some(discord::UNKNOWN_CHANNEL | discord::MISSING_ACCESS),
+                        Subscriber::Channel(_),
+                    ) => {
+                        tracing::info!(?subscriber, %err, "channel no longer accessible");
+                        true
+                    }
+                    _ => false,
+                };
+
+                if unreachable {
+                    let _ = db_client
+                        .set_notification_status(
+                            &notification.id,
+                            NotificationStatus::Failed,
+                            attempts,
+                        )
+                        .await;
+                    for rest in queue[i + 1..].iter() {
+                        let _ = db_client
+                            .set_notification_status(
+                                &rest.id,
+                                NotificationStatus::Failed,
+                                rest.attempts,
+                            )
+                            .await;
+                    }
+                    return false;
+                }
+
+                if attempts < MAX_DELIVERY_ATTEMPTS {
+                    tracing::warn!(?subscriber, %err, attempts, "failed to deliver notification");
+                    let _ = db_client
+                        .set_notification_status(
+                            &notification.id,
+                            NotificationStatus::Pending,
+                            attempts,
+                        )
+                        .await;
+                    return true;
+                }
+
+                tracing::warn!(?subscriber, %err, attempts, "giving up on notification");
+                NotificationStatus::Failed
+            }
+        };
+
+        let _ = db_client
+            .set_notification_status(&notification.id, status, attempts)
+            .await;
     }
 
+    true
+}
+
+/// Delivers a notification to its subscriber, either as a single summary message if it
+/// covers many chapters or as a message about its only chapter.
+async fn deliver(http: &Http, notification: &Notification) -> serenity::Result<()> {
+    let channel_id = match notification.subscriber {
+        Subscriber::Channel(channel_id) => channel_id,
+        Subscriber::User(user_id) => user_id.create_dm_channel(http).await?.id,
+    };
+
+    match notification.chapters.as_slice() {
+        [chapter] => send_update_message(http, notification, chapter, channel_id).await,
+        _ => send_bulk_update_message(http, notification, channel_id).await,
+    }
+}
+
+/// Checks whether manga with a given content rating may be announced to a subscription.
+///
+/// Manga with an unknown content rating are always allowed.
+async fn is_content_rating_allowed(
+    http: &Http,
+    guild_settings: &HashMap<GuildId, GuildSettings>,
+    subscription: &Subscription,
+    content_rating: Option<ContentRating>,
+) -> bool {
+    let Some(content_rating) = content_rating else {
+        return true;
+    };
+
+    let allowed = match subscription.guild_id.and_then(|g| guild_settings.get(&g)) {
+        Some(settings) => settings.content_ratings.contains(&content_rating),
+        None => ContentRating::DEFAULT.contains(&content_rating),
+    };
+
+    if !allowed || !content_rating.is_nsfw() {
+        return allowed;
+    }
+
+    // Direct messages can't be marked as NSFW.
+    let Subscriber::Channel(channel_id) = subscription.subscriber else {
+        return false;
+    };
+
+    match channel_id.to_channel(http).await {
+        Ok(channel) => channel.is_nsfw(),
+        Err(err) => {
+            tracing::warn!(%err, ?subscription, "failed to check if channel is NSFW");
+            false
+        }
+    }
+}
+
+/// Finds the chapters for a given manga in a given language which are newer than the latest
+/// chapter that has already been announced, ordered by chapter number and then by when they
+/// were published.
+///
+/// A chapter is considered new if it was published after the latest known chapter and, if
+/// both have chapter numbers, has a greater chapter number. Only the first chapter published
+/// for any given chapter number is kept so that multiple translations of the same chapter are
+/// not announced more than once.
+fn new_chapters<'a>(manga: &Manga, language: &str, chapters: &'a [Chapter]) -> Vec<&'a Chapter> {
+    let marker = manga.latest_chapters.get(language);
+    let latest_id = marker.map(|m| m.id.as_str());
+    let latest_publish_at = marker.and_then(|m| m.publish_at.as_deref());
+    let latest_number = marker
+        .and_then(|m| m.number.as_deref())
+        .and_then(parse_chapter_number);
+
+    let mut new_chapters: Vec<&Chapter> = chapters
+        .iter()
+        .filter(|c| c.manga_id() == Some(manga.id.as_str()))
+        .filter(|c| c.attributes.translated_language.as_deref() == Some(language))
+        .filter(|c| Some(c.id.as_str()) != latest_id)
+        .filter(
+            |c| match (latest_publish_at, c.attributes.publish_at.as_deref()) {
+                (Some(latest), Some(publish_at)) => publish_at > latest,
+                _ => true,
+            },
+        )
+        .filter(|c| match (latest_number, chapter_number(c)) {
+            (Some(latest), Some(number)) => number > latest,
+            _ => true,
+        })
+        .collect();
+
+    // Chapters are already ordered by when they were published so a stable sort by chapter
+    // number keeps the earliest translation of each chapter first.
+    new_chapters.sort_by(|a, b| {
+        let a = chapter_number(a).unwrap_or(f64::MAX);
+        let b = chapter_number(b).unwrap_or(f64::MAX);
+        a.total_cmp(&b)
+    });
+    new_chapters
+        .dedup_by(|a, b| chapter_number(a).is_some() && chapter_number(a) == chapter_number(b));
+
+    new_chapters
+}
+
+/// Gets the numeric chapter number of a chapter if it has one.
+fn chapter_number(chapter: &Chapter) -> Option<f64> {
+    chapter
+        .attributes
+        .chapter
+        .as_deref()
+        .and_then(parse_chapter_number)
+}
+
+/// Parses a chapter number (e.g., "12" or "12.5").
+fn parse_chapter_number(number: &str) -> Option<f64> {
+    number.trim().parse().ok()
+}
+
+/// Sends a single message to a specific channel summarizing many new chapters.
+#[tracing::instrument(err, skip_all, fields(notification = notification.id))]
+async fn send_bulk_update_message(
+    http: &Http,
+    notification: &Notification,
+    channel_id: ChannelId,
+) -> serenity::Result<()> {
+    let chapters = &notification.chapters;
+    let count = chapters.len();
+    let url = mangadex::manga_url(&notification.manga_id);
+    let first = chapters.first().and_then(|c| c.number.as_deref());
+    let last = chapters.last().and_then(|c| c.number.as_deref());
+
+    channel_id
+        .send_message(http, |message| {
+            mention_role(message, notification.role_id)
+                .embed(|embed| {
+                    embed
+                        .title(&notification.manga_title)
+                        .url(&url)
+                        .description(format!("{count} new chapters!"));
+
+                    if let (Some(first), Some(last)) = (first, last) {
+                        embed.field("Chapters", format!("{first} - {last}"), true);
+                    }
+
+                    if let Some(cover_url) = notification.cover_url.as_deref() {
+                        embed.thumbnail(cover_url);
+                    }
+
+                    embed
+                })
+                .components(|components| read_button(components, &url))
+        })
+        .await?;
     Ok(())
 }
 
 /// Sends a message to a specific channel about a new chapter update.
-#[tracing::instrument(err, skip(http))]
+#[tracing::instrument(err, skip_all, fields(notification = notification.id))]
 async fn send_update_message(
     http: &Http,
-    manga: &Manga,
-    chapter: &Chapter,
-    channel: ChannelId,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let manga_title = manga.title.as_str();
-    let url = chapter.url();
-    let message = match &chapter.attributes {
-        ChapterAttributes {
-            chapter: Some(ch),
-            title: Some(title),
-            ..
-        } => format!("New chapter!\n{manga_title} ch. {ch}: {title}"),
-        ChapterAttributes {
-            chapter: Some(ch), ..
-        } => format!("New chapter!\n{manga_title} ch. {ch}"),
-        _ => format!("New chapter for {manga_title}!"),
+    notification: &Notification,
+    chapter: &AnnouncedChapter,
+    channel_id: ChannelId,
+) -> serenity::Result<()> {
+    let url = mangadex::chapter_url(&chapter.id);
+    let description = match (chapter.number.as_deref(), chapter.title.as_deref()) {
+        (Some(ch), Some(title)) => format!("New chapter!\nCh. {ch}: {title}"),
+        (Some(ch), None) => format!("New chapter!\nCh. {ch}"),
+        (None, Some(title)) => format!("New chapter!\n{title}"),
+        (None, None) => String::from("New chapter!"),
     };
 
-    channel.say(http, format!("{message}\n{url}")).await?;
+    channel_id
+        .send_message(http, |message| {
+            mention_role(message, notification.role_id)
+                .embed(|embed| {
+                    embed
+                        .title(&notification.manga_title)
+                        .url(&url)
+                        .description(description);
+
+                    if let Some(volume) = chapter.volume.as_deref() {
+                        embed.field("Volume", volume, true);
+                    }
+
+                    if let Some(ch) = chapter.number.as_deref() {
+                        embed.field("Chapter", ch, true);
+                    }
+
+                    embed.field("Pages", chapter.pages, true);
+
+                    if let Some(group) = chapter.group.as_deref() {
+                        embed.field("Group", group, true);
+                    }
+
+                    if let Some(cover_url) = notification.cover_url.as_deref() {
+                        embed.thumbnail(cover_url);
+                    }
+
+                    if let Some(readable_at) = chapter
+                        .readable_at
+                        .as_deref()
+                        .and_then(|t| Timestamp::parse(t).ok())
+                    {
+                        embed.timestamp(readable_at);
+                    }
+
+                    embed
+                })
+                .components(|components| read_button(components, &url))
+        })
+        .await?;
     Ok(())
 }
+
+/// Mentions a role in a message, if given, making sure that no one else is pinged.
+fn mention_role<'a, 'b>(
+    message: &'b mut CreateMessage<'a>,
+    role_id: Option<RoleId>,
+) -> &'b mut CreateMessage<'a> {
+    if let Some(role_id) = role_id {
+        message.content(role_id.mention());
+    }
+
+    message.allowed_mentions(|mentions| mentions.empty_parse().roles(role_id))
+}
+
+/// Adds a link button that opens a given URL on MangaDex.
+fn read_button<'a>(components: &'a mut CreateComponents, url: &Url) -> &'a mut CreateComponents {
+    components.create_action_row(|row| {
+        row.create_button(|button| button.style(ButtonStyle::Link).label("Read").url(url))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::prelude::{ChannelId, GuildId, UserId};
+    use time::format_description::well_known::Rfc3339;
+    use time::macros::datetime;
+
+    use crate::db::MemoryStore;
+    use crate::mock::{chapter_json, manga_json, MockDiscord, MockMangaDex, MockOutbox};
+
+    use super::*;
+
+    const MANGA_ID: &str = "a96676e5-8ae2-425e-b549-7f15dd34a6d8";
+    const CHANNEL_ID: u64 = 1234;
+
+    fn tracked_manga(latest_chapter: &str) -> Manga {
+        let marker = ChapterMarker {
+            id: String::from(latest_chapter),
+            number: Some(String::from("1")),
+            publish_at: Some(String::from("2023-01-01T00:00:00+00:00")),
+        };
+
+        Manga {
+            id: String::from(MANGA_ID),
+            title: String::from("Komi Can't Communicate"),
+            latest_chapters: HashMap::from([(String::from("en"), marker)]),
+            subscribers: vec![Subscription {
+                subscriber: Subscriber::Channel(ChannelId(CHANNEL_ID)),
+                guild_id: Some(GuildId(1)),
+                language: String::from("en"),
+                role_id: None,
+            }],
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            period: Duration::from_secs(60),
+            bulk_threshold: 5,
+            concurrency: 4,
+        }
+    }
+
+    /// Checks for chapters published since the start of 2023 with the default config.
+    async fn run_scan(
+        discord: &MockDiscord,
+        db_client: &impl Store,
+        mangadex: &MockMangaDex,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        run_scan_with(
+            discord,
+            db_client,
+            mangadex,
+            &config(),
+            &CancellationToken::new(),
+        )
+        .await
+    }
+
+    /// Checks for chapters published since the start of 2023.
+    async fn run_scan_with(
+        discord: &MockDiscord,
+        db_client: &impl Store,
+        mangadex: &MockMangaDex,
+        config: &Config,
+        shutdown: &CancellationToken,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        check_for_updates(
+            &discord.http(),
+            db_client,
+            &mangadex.client(),
+            config,
+            datetime!(2023-01-01 00:00 UTC),
+            shutdown,
+        )
+        .await
+    }
+
+    fn pending_notification(manga: &Manga, chapter_id: &str) -> Notification {
+        let chapter = AnnouncedChapter {
+            id: String::from(chapter_id),
+            number: Some(String::from("2")),
+            title: None,
+            volume: None,
+            pages: 20,
+            group: None,
+            readable_at: None,
+        };
+
+        let queued_at = OffsetDateTime::now_utc().unix_timestamp();
+        Notification::new(
+            manga,
+            &manga.subscribers[0],
+            vec![chapter],
+            None,
+            queued_at,
+            0,
+        )
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_announces_new_chapters() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex
+            .manga(manga_json(MANGA_ID, "Komi Can't Communicate", "safe"))
+            .await;
+        mangadex
+            .chapters_published_since(
+                MANGA_ID,
+                vec![
+                    chapter_json("ch-1", MANGA_ID, "1", "en", "2023-01-01T00:00:00+00:00"),
+                    chapter_json("ch-2", MANGA_ID, "2", "en", "2023-01-02T00:00:00+00:00"),
+                    chapter_json("ch-3", MANGA_ID, "3", "en", "2023-01-03T00:00:00+00:00"),
+                ],
+            )
+            .await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(CHANNEL_ID, 2).await;
+
+        let db_client = MemoryStore::new();
+        db_client.create_manga(tracked_manga("ch-1")).await.unwrap();
+
+        run_scan(&discord, &db_client, &mangadex).await.unwrap();
+
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        let marker = &manga.latest_chapters["en"];
+        assert_eq!(marker.id, "ch-3");
+        assert_eq!(marker.number.as_deref(), Some("3"));
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_announces_chapters_separately_up_to_bulk_threshold() {
+        let chapters = (2..7)
+            .map(|n| {
+                let at = format!("2023-01-0{n}T00:00:00+00:00");
+                chapter_json(&format!("ch-{n}"), MANGA_ID, &n.to_string(), "en", &at)
+            })
+            .collect();
+        let mangadex = MockMangaDex::start().await;
+        mangadex
+            .manga(manga_json(MANGA_ID, "Komi Can't Communicate", "safe"))
+            .await;
+        mangadex.chapters_published_since(MANGA_ID, chapters).await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(CHANNEL_ID, 5).await;
+
+        let db_client = MemoryStore::new();
+        db_client.create_manga(tracked_manga("ch-1")).await.unwrap();
+
+        run_scan(&discord, &db_client, &mangadex).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_summarizes_chapters_above_bulk_threshold() {
+        let chapters = (2..8)
+            .map(|n| {
+                let at = format!("2023-01-0{n}T00:00:00+00:00");
+                chapter_json(&format!("ch-{n}"), MANGA_ID, &n.to_string(), "en", &at)
+            })
+            .collect();
+        let mangadex = MockMangaDex::start().await;
+        mangadex
+            .manga(manga_json(MANGA_ID, "Komi Can't Communicate", "safe"))
+            .await;
+        mangadex.chapters_published_since(MANGA_ID, chapters).await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(CHANNEL_ID, 1).await;
+
+        let db_client = MemoryStore::new();
+        db_client.create_manga(tracked_manga("ch-1")).await.unwrap();
+
+        run_scan(&discord, &db_client, &mangadex).await.unwrap();
+
+        let messages = discord.sent_messages(CHANNEL_ID).await;
+        let embed = &messages[0]["embeds"][0];
+        assert_eq!(embed["description"], "6 new chapters!");
+        assert_eq!(embed["fields"][0]["value"], "2 - 7");
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(manga.latest_chapters["en"].id, "ch-7");
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_without_new_chapters() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex.chapters_published_since(MANGA_ID, vec![]).await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(CHANNEL_ID, 0).await;
+
+        let db_client = MemoryStore::new();
+        db_client.create_manga(tracked_manga("ch-1")).await.unwrap();
+
+        run_scan(&discord, &db_client, &mangadex).await.unwrap();
+
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(manga.latest_chapters["en"].id, "ch-1");
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_stops_on_shutdown() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex
+            .chapters_published_since(
+                MANGA_ID,
+                vec![chapter_json(
+                    "ch-2",
+                    MANGA_ID,
+                    "2",
+                    "en",
+                    "2023-01-02T00:00:00+00:00",
+                )],
+            )
+            .await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(CHANNEL_ID, 0).await;
+
+        let db_client = MemoryStore::new();
+        db_client.create_manga(tracked_manga("ch-1")).await.unwrap();
+
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+        run_scan_with(&discord, &db_client, &mangadex, &config(), &shutdown)
+            .await
+            .unwrap();
+
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(manga.latest_chapters["en"].id, "ch-1");
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_removes_deleted_channels() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex
+            .manga(manga_json(MANGA_ID, "Komi Can't Communicate", "safe"))
+            .await;
+        mangadex
+            .chapters_published_since(
+                MANGA_ID,
+                vec![chapter_json(
+                    "ch-2",
+                    MANGA_ID,
+                    "2",
+                    "en",
+                    "2023-01-02T00:00:00+00:00",
+                )],
+            )
+            .await;
+
+        let discord = MockDiscord::start().await;
+        discord
+            .reject_messages(CHANNEL_ID, 404, discord::UNKNOWN_CHANNEL)
+            .await;
+
+        let db_client = MemoryStore::new();
+        db_client.create_manga(tracked_manga("ch-1")).await.unwrap();
+
+        run_scan(&discord, &db_client, &mangadex).await.unwrap();
+
+        // The channel was the only subscriber so the manga is no longer tracked.
+        assert!(db_client.read_manga(MANGA_ID).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_delivers_pending_notifications_once() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex.chapters_published_since(MANGA_ID, vec![]).await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(CHANNEL_ID, 1).await;
+
+        // Left in the outbox by a previous run which stopped before delivering it.
+        let db_client = MemoryStore::new();
+        let manga = tracked_manga("ch-1");
+        let marker = ChapterMarker {
+            id: String::from("ch-2"),
+            ..ChapterMarker::default()
+        };
+        db_client.create_manga(manga.clone()).await.unwrap();
+        db_client
+            .enqueue_notifications(
+                MANGA_ID,
+                "en",
+                &marker,
+                &[pending_notification(&manga, "ch-2")],
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            run_scan(&discord, &db_client, &mangadex).await.unwrap();
+        }
+
+        let notification = db_client.notification("channel-1234:ch-2").unwrap();
+        assert_eq!(notification.status, NotificationStatus::Sent);
+        assert_eq!(notification.attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_skips_interrupted_notifications() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex.chapters_published_since(MANGA_ID, vec![]).await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(CHANNEL_ID, 0).await;
+
+        let db_client = MemoryStore::new();
+        let manga = tracked_manga("ch-1");
+        let notification = pending_notification(&manga, "ch-2");
+        db_client.create_manga(manga).await.unwrap();
+        db_client
+            .enqueue_notifications(
+                MANGA_ID,
+                "en",
+                &ChapterMarker::default(),
+                std::slice::from_ref(&notification),
+            )
+            .await
+            .unwrap();
+        assert!(db_client
+            .claim_notification(&notification.id, 1)
+            .await
+            .unwrap());
+        assert!(!db_client
+            .claim_notification(&notification.id, 1)
+            .await
+            .unwrap());
+
+        assert_eq!(db_client.fail_interrupted_notifications().await.unwrap(), 1);
+
+        run_scan(&discord, &db_client, &mangadex).await.unwrap();
+
+        let notification = db_client.notification(&notification.id).unwrap();
+        assert_eq!(notification.status, NotificationStatus::Failed);
+        assert_eq!(notification.attempts, 1);
+        assert!(db_client
+            .read_pending_notifications()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_drops_notifications_for_removed_subscriptions() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex.chapters_published_since(MANGA_ID, vec![]).await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(5678, 0).await;
+
+        // The channel stopped tracking the manga after the notification was queued.
+        let mut untracked = tracked_manga("ch-1");
+        untracked.subscribers[0].subscriber = Subscriber::Channel(ChannelId(5678));
+        let notification = pending_notification(&untracked, "ch-2");
+
+        let db_client = MemoryStore::new();
+        db_client.create_manga(tracked_manga("ch-1")).await.unwrap();
+        db_client
+            .enqueue_notifications(
+                MANGA_ID,
+                "en",
+                &ChapterMarker::default(),
+                std::slice::from_ref(&notification),
+            )
+            .await
+            .unwrap();
+
+        run_scan(&discord, &db_client, &mangadex).await.unwrap();
+
+        let notification = db_client.notification(&notification.id).unwrap();
+        assert_eq!(notification.status, NotificationStatus::Failed);
+        assert_eq!(notification.attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn scan_gives_up_on_interrupted_notifications_when_starting() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex.chapters_published_since(MANGA_ID, vec![]).await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(CHANNEL_ID, 1).await;
+
+        let db_client = Arc::new(MemoryStore::new());
+        let manga = tracked_manga("ch-1");
+        let interrupted = pending_notification(&manga, "ch-2");
+        let mut pending = pending_notification(&manga, "ch-3");
+        pending.position = 1;
+        db_client.create_manga(manga).await.unwrap();
+        db_client
+            .enqueue_notifications(
+                MANGA_ID,
+                "en",
+                &ChapterMarker::default(),
+                &[interrupted.clone(), pending.clone()],
+            )
+            .await
+            .unwrap();
+        // The previous run stopped while sending the first notification.
+        assert!(db_client
+            .claim_notification(&interrupted.id, 1)
+            .await
+            .unwrap());
+
+        let shutdown = CancellationToken::new();
+        let health = Arc::new(Health::new());
+        let task = tokio::spawn(scan(
+            Arc::new(discord.http()),
+            Arc::clone(&db_client),
+            Arc::new(mangadex.client()),
+            Arc::clone(&health),
+            config(),
+            shutdown.clone(),
+        ));
+        let first_scan = async {
+            while health.last_scan_age().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), first_scan)
+            .await
+            .expect("the first scan should succeed");
+        shutdown.cancel();
+        task.await.unwrap();
+
+        let interrupted = db_client.notification(&interrupted.id).unwrap();
+        assert_eq!(interrupted.status, NotificationStatus::Failed);
+        assert_eq!(interrupted.attempts, 1);
+        let pending = db_client.notification(&pending.id).unwrap();
+        assert_eq!(pending.status, NotificationStatus::Sent);
+        assert_eq!(pending.attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_keeps_undelivered_notifications() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex
+            .manga(manga_json(MANGA_ID, "Komi Can't Communicate", "safe"))
+            .await;
+        mangadex
+            .chapters_published_since(
+                MANGA_ID,
+                vec![chapter_json(
+                    "ch-2",
+                    MANGA_ID,
+                    "2",
+                    "en",
+                    "2023-01-02T00:00:00+00:00",
+                )],
+            )
+            .await;
+
+        let discord = MockDiscord::start().await;
+        discord.reject_messages(CHANNEL_ID, 400, 0).await;
+
+        let db_client = MemoryStore::new();
+        db_client.create_manga(tracked_manga("ch-1")).await.unwrap();
+
+        run_scan(&discord, &db_client, &mangadex).await.unwrap();
+
+        // The chapter is recorded as announced but stays in the outbox to be retried.
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(manga.latest_chapters["en"].id, "ch-2");
+
+        let pending = db_client.read_pending_notifications().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].attempts, 1);
+        assert_eq!(pending[0].chapters[0].id, "ch-2");
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_checks_manga_concurrently() {
+        const MANGA: [(&str, u64); 4] = [
+            (MANGA_ID, CHANNEL_ID),
+            ("0e9ba5b4-3dc2-4d3e-a5b3-9d1d3c4a4b1f", 5678),
+            ("2e0fdb3b-632c-4f8f-a311-5b56952db647", 6789),
+            ("6b958848-c885-4735-9201-12ee77abcb3c", 7890),
+        ];
+
+        let mangadex = MockMangaDex::start().await;
+        let discord = MockDiscord::start().await;
+        // Queuing the announcements for each manga is slow enough for the manga being checked
+        // at the same time to catch up.
+        let db_client = MockOutbox::slow(Duration::from_millis(200));
+        let mut chapters = Vec::new();
+        for (i, (manga_id, channel_id)) in MANGA.into_iter().enumerate() {
+            mangadex
+                .manga(manga_json(manga_id, &format!("Manga {i}"), "safe"))
+                .await;
+            chapters.push(chapter_json(
+                &format!("new-{i}"),
+                manga_id,
+                "2",
+                "en",
+                "2023-01-02T00:00:00+00:00",
+            ));
+            discord.expect_messages(channel_id, 1).await;
+
+            let mut manga = tracked_manga("ch-1");
+            manga.id = String::from(manga_id);
+            manga.subscribers[0].subscriber = Subscriber::Channel(ChannelId(channel_id));
+            db_client.create_manga(manga).await.unwrap();
+        }
+        // Chapters for every manga are fetched with a single request.
+        mangadex.chapters_published_since(MANGA_ID, chapters).await;
+
+        run_scan_with(
+            &discord,
+            &db_client,
+            &mangadex,
+            &Config {
+                concurrency: 2,
+                ..config()
+            },
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(db_client.peak_enqueues(), 2);
+        for (i, (manga_id, _)) in MANGA.into_iter().enumerate() {
+            let manga = db_client.read_manga(manga_id).await.unwrap().unwrap();
+            assert_eq!(manga.latest_chapters["en"].id, format!("new-{i}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_fails_if_chapters_cannot_be_queued() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex
+            .manga(manga_json(MANGA_ID, "Komi Can't Communicate", "safe"))
+            .await;
+        mangadex
+            .chapters_published_since(
+                MANGA_ID,
+                vec![chapter_json(
+                    "ch-2",
+                    MANGA_ID,
+                    "2",
+                    "en",
+                    "2023-01-02T00:00:00+00:00",
+                )],
+            )
+            .await;
+
+        let discord = MockDiscord::start().await;
+        discord.expect_messages(CHANNEL_ID, 0).await;
+
+        let db_client = MockOutbox::broken();
+        db_client.create_manga(tracked_manga("ch-1")).await.unwrap();
+
+        let result = run_scan(&discord, &db_client, &mangadex).await;
+        assert!(result.is_err());
+
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(manga.latest_chapters["en"].id, "ch-1");
+    }
+
+    #[tokio::test]
+    async fn check_for_updates_records_guilds_of_legacy_subscriptions() {
+        let mangadex = MockMangaDex::start().await;
+        mangadex.chapters_published_since(MANGA_ID, vec![]).await;
+
+        let discord = MockDiscord::start().await;
+        discord.channel(CHANNEL_ID, 1).await;
+
+        // Subscriptions used to be stored as bare channel ids without a guild.
+        let mut manga = tracked_manga("ch-1");
+        manga.subscribers =
+            serde_json::from_value(serde_json::json!([CHANNEL_ID.to_string()])).unwrap();
+        assert_eq!(manga.subscribers[0].guild_id, None);
+
+        let db_client = MemoryStore::new();
+        db_client.create_manga(manga).await.unwrap();
+
+        run_scan(&discord, &db_client, &mangadex).await.unwrap();
+
+        let manga = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(manga.subscribers[0].guild_id, Some(GuildId(1)));
+
+        // And so the subscription is removed along with the guild.
+        db_client.remove_guild(GuildId(1)).await.unwrap();
+        assert!(db_client.read_manga(MANGA_ID).await.unwrap().is_none());
+    }
+
+    /// Tracks a manga whose latest chapter was published a given time ago.
+    async fn tracked_manga_published(db_client: &MemoryStore, ago: Duration) -> OffsetDateTime {
+        let published = OffsetDateTime::now_utc() - ago;
+        let mut manga = tracked_manga("ch-1");
+        manga.latest_chapters.get_mut("en").unwrap().publish_at =
+            Some(published.format(&Rfc3339).unwrap());
+        db_client.create_manga(manga).await.unwrap();
+        published
+    }
+
+    #[tokio::test]
+    async fn initial_since_goes_back_to_oldest_latest_chapter() {
+        let db_client = MemoryStore::new();
+        let published = tracked_manga_published(&db_client, Duration::from_secs(2 * 60 * 60)).await;
+
+        assert_eq!(initial_since(&db_client, &config()).await, published);
+    }
+
+    #[tokio::test]
+    async fn initial_since_is_bounded() {
+        let db_client = MemoryStore::new();
+        let since = initial_since(&db_client, &config()).await;
+        assert_close(since, OffsetDateTime::now_utc() - config().period);
+
+        tracked_manga_published(&db_client, Duration::from_secs(1)).await;
+        let since = initial_since(&db_client, &config()).await;
+        assert_close(since, OffsetDateTime::now_utc() - config().period);
+
+        let db_client = MemoryStore::new();
+        tracked_manga_published(&db_client, MAX_CATCH_UP * 4).await;
+        let since = initial_since(&db_client, &config()).await;
+        assert_close(since, OffsetDateTime::now_utc() - MAX_CATCH_UP);
+    }
+
+    fn assert_close(actual: OffsetDateTime, expected: OffsetDateTime) {
+        assert!(
+            (actual - expected).abs() < time::Duration::SECOND,
+            "{actual} != {expected}"
+        );
+    }
+
+    #[tokio::test]
+    async fn backfill_guild_ids_keeps_concurrent_changes() {
+        let discord = MockDiscord::start().await;
+        discord.channel(CHANNEL_ID, 1).await;
+
+        let mut manga = tracked_manga("ch-1");
+        manga.subscribers[0].guild_id = None;
+        let db_client = MemoryStore::new();
+        db_client.create_manga(manga.clone()).await.unwrap();
+
+        // Someone else subscribes after the scan read the manga.
+        let user = Subscription {
+            subscriber: Subscriber::User(UserId(9)),
+            guild_id: None,
+            language: String::from("en"),
+            role_id: None,
+        };
+        let mut subscribers = manga.subscribers.clone();
+        subscribers.push(user.clone());
+        db_client
+            .set_subscriptions(MANGA_ID, &subscribers)
+            .await
+            .unwrap();
+
+        backfill_guild_ids(
+            &discord.http(),
+            &db_client,
+            std::slice::from_mut(&mut manga),
+        )
+        .await;
+
+        assert_eq!(manga.subscribers[0].guild_id, Some(GuildId(1)));
+        let stored = db_client.read_manga(MANGA_ID).await.unwrap().unwrap();
+        assert_eq!(stored.subscribers[0].guild_id, Some(GuildId(1)));
+        assert_eq!(stored.subscribers[1], user);
+    }
+
+    fn chapter(id: &str, number: Option<&str>, at: &str) -> Chapter {
+        let mut chapter = chapter_json(id, MANGA_ID, "", "en", at);
+        chapter["attributes"]["chapter"] = number.into();
+        serde_json::from_value(chapter).unwrap()
+    }
+
+    fn ids<'a>(chapters: &[&'a Chapter]) -> Vec<&'a str> {
+        chapters.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    #[test]
+    fn new_chapters_skips_announced_chapters() {
+        let other_language = chapter_json("fr-2", MANGA_ID, "2", "fr", "2023-01-02T00:00:00+00:00");
+        let other_manga = chapter_json(
+            "other-2",
+            "0e9ba5b4-3dc2-4d3e-a5b3-9d1d3c4a4b1f",
+            "2",
+            "en",
+            "2023-01-02T00:00:00+00:00",
+        );
+        let chapters = [
+            chapter("ch-1", Some("1"), "2023-01-01T00:00:00+00:00"),
+            // Published before the latest chapter, e.g. a late upload of an older chapter.
+            chapter("early-2", Some("2"), "2022-12-31T00:00:00+00:00"),
+            // Published after the latest chapter but not numbered after it.
+            chapter("reupload-1", Some("1"), "2023-01-02T00:00:00+00:00"),
+            serde_json::from_value(other_language).unwrap(),
+            serde_json::from_value(other_manga).unwrap(),
+            chapter("ch-2", Some("2"), "2023-01-02T00:00:00+00:00"),
+        ];
+
+        let new_chapters = new_chapters(&tracked_manga("ch-1"), "en", &chapters);
+
+        assert_eq!(ids(&new_chapters), ["ch-2"]);
+    }
+
+    #[test]
+    fn new_chapters_keeps_first_translation_of_each_chapter() {
+        let chapters = [
+            chapter("group-b-3", Some("3"), "2023-01-02T00:00:00+00:00"),
+            chapter("group-a-2", Some("2"), "2023-01-03T00:00:00+00:00"),
+            chapter("group-b-2", Some("2"), "2023-01-04T00:00:00+00:00"),
+            chapter("group-a-3", Some("3"), "2023-01-05T00:00:00+00:00"),
+        ];
+
+        let new_chapters = new_chapters(&tracked_manga("ch-1"), "en", &chapters);
+
+        assert_eq!(ids(&new_chapters), ["group-a-2", "group-b-3"]);
+    }
+
+    #[test]
+    fn new_chapters_sorts_unnumbered_chapters_last() {
+        let chapters = [
+            chapter("oneshot", None, "2023-01-02T00:00:00+00:00"),
+            chapter("ch-3", Some("3"), "2023-01-03T00:00:00+00:00"),
+            chapter("ch-2", Some("2"), "2023-01-04T00:00:00+00:00"),
+            chapter("extra", None, "2023-01-05T00:00:00+00:00"),
+        ];
+
+        let new_chapters = new_chapters(&tracked_manga("ch-1"), "en", &chapters);
+
+        assert_eq!(ids(&new_chapters), ["ch-2", "ch-3", "oneshot", "extra"]);
+    }
+}
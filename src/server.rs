@@ -0,0 +1,188 @@
+//! The `server` module contains an optional embedded HTTP server exposing metrics about the
+//! bot in the Prometheus text format, along with endpoints reporting its health and readiness.
+//!
+//! Metrics are recorded throughout the application using the macros from the [metrics]
+//! crate. They are discarded unless a recorder has been installed with [install_recorder].
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use metrics::Unit;
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use serde::Serialize;
+
+use crate::{db::Store, health::Health};
+
+/// How long to wait for the database to respond when checking whether it can be reached.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The buckets, in seconds, for histograms of how long something took.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Installs the global recorder that metrics are collected by, returning a handle which
+/// renders them.
+pub fn install_recorder() -> Result<PrometheusHandle, BuildError> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix(String::from("duration_seconds")),
+            DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    describe_metrics();
+    Ok(handle)
+}
+
+/// Registers the unit and description of every metric recorded by the application.
+fn describe_metrics() {
+    metrics::describe_histogram!(
+        "mangadex_bot_scan_duration_seconds",
+        Unit::Seconds,
+        "How long each scan for new chapters took."
+    );
+    metrics::describe_gauge!(
+        "mangadex_bot_scan_manga_checked",
+        Unit::Count,
+        "The number of manga checked for new chapters by the last scan."
+    );
+    metrics::describe_counter!(
+        "mangadex_bot_mangadex_requests_total",
+        Unit::Count,
+        "Requests sent to the MangaDex API by endpoint and response status."
+    );
+    metrics::describe_histogram!(
+        "mangadex_bot_mangadex_request_duration_seconds",
+        Unit::Seconds,
+        "How long requests to the MangaDex API took by endpoint and status."
+    );
+    metrics::describe_counter!(
+        "mangadex_bot_notifications_total",
+        Unit::Count,
+        "Chapter announcements by whether they were sent or failed."
+    );
+    metrics::describe_counter!(
+        "mangadex_bot_commands_total",
+        Unit::Count,
+        "Application command invocations by command name and outcome."
+    );
+    metrics::describe_gauge!(
+        "mangadex_bot_tracked_manga",
+        Unit::Count,
+        "The number of manga tracked by at least one channel or user, as of the last scan."
+    );
+    metrics::describe_gauge!(
+        "mangadex_bot_tracked_channels",
+        Unit::Count,
+        "The number of channels tracking at least one manga, as of the last scan."
+    );
+}
+
+/// The state shared by the server's handlers.
+struct ServerState<S> {
+    metrics: PrometheusHandle,
+    health: Arc<Health>,
+    db_client: Arc<S>,
+    /// How long scanning may go without succeeding before the bot is no longer ready.
+    stall_threshold: Duration,
+}
+
+/// The health of the bot as reported by `/healthz` and `/readyz`.
+#[derive(Debug, Serialize)]
+struct HealthReport {
+    discord_connected: bool,
+    database_reachable: bool,
+    /// Seconds since the last successful scan for new chapters, if any.
+    last_scan_age_seconds: Option<u64>,
+    scan_stalled: bool,
+}
+
+impl HealthReport {
+    /// Whether the bot is able to do its job.
+    fn is_ready(&self) -> bool {
+        self.discord_connected && self.database_reachable && !self.scan_stalled
+    }
+}
+
+/// Starts serving metrics from `/metrics`, and health and readiness reports from `/healthz`
+/// and `/readyz`, on a given address in the background.
+///
+/// The bot is only reported as ready if scanning has succeeded within `stall_threshold`.
+pub fn spawn<S: Store>(
+    address: SocketAddr,
+    metrics: PrometheusHandle,
+    health: Arc<Health>,
+    db_client: Arc<S>,
+    stall_threshold: Duration,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let state = ServerState {
+        metrics,
+        health,
+        db_client,
+        stall_threshold,
+    };
+    let app = Router::new()
+        .route("/metrics", get(render_metrics::<S>))
+        .route("/healthz", get(healthz::<S>))
+        .route("/readyz", get(readyz::<S>))
+        .with_state(Arc::new(state));
+
+    let server = axum::Server::try_bind(&address)?.serve(app.into_make_service());
+    tracing::info!(%address, "serving metrics and health reports");
+
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            tracing::error!(%err, "HTTP server failed");
+        }
+    });
+
+    Ok(())
+}
+
+/// Renders every metric in the Prometheus text format.
+async fn render_metrics<S: Store>(State(state): State<Arc<ServerState<S>>>) -> String {
+    state.metrics.render()
+}
+
+/// Reports the health of the bot, always succeeding as long as the bot is running.
+async fn healthz<S: Store>(State(state): State<Arc<ServerState<S>>>) -> Json<HealthReport> {
+    Json(report(&state).await)
+}
+
+/// Reports the health of the bot, failing if it isn't able to do its job.
+async fn readyz<S: Store>(
+    State(state): State<Arc<ServerState<S>>>,
+) -> (StatusCode, Json<HealthReport>) {
+    let report = report(&state).await;
+    let status = if report.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(report))
+}
+
+/// Checks on the health of the bot.
+async fn report<S: Store>(state: &ServerState<S>) -> HealthReport {
+    let database_reachable = match tokio::time::timeout(PING_TIMEOUT, state.db_client.ping()).await
+    {
+        Ok(Ok(())) => true,
+        Ok(Err(err)) => {
+            tracing::warn!(%err, "database is unreachable");
+            false
+        }
+        Err(_) => {
+            tracing::warn!(timeout = ?PING_TIMEOUT, "database ping timed out");
+            false
+        }
+    };
+
+    HealthReport {
+        discord_connected: state.health.is_connected(),
+        database_reachable,
+        last_scan_age_seconds: state.health.last_scan_age().map(|age| age.as_secs()),
+        scan_stalled: state.health.is_scan_stalled(state.stall_threshold),
+    }
+}